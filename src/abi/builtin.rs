@@ -0,0 +1,183 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::{
+    abi::{parse_abi, Abi},
+    addressbook::Chain,
+    types::Address,
+};
+
+use super::AbiSource;
+
+const UNISWAP_V2_FACTORY: &[&str] = &[
+    "event PairCreated(address indexed token0, address indexed token1, address pair, uint)",
+    "function getPair(address tokenA, address tokenB) external view returns (address pair)",
+    "function allPairs(uint) external view returns (address pair)",
+    "function allPairsLength() external view returns (uint)",
+    "function createPair(address tokenA, address tokenB) external returns (address pair)",
+    "function feeTo() external view returns (address)",
+];
+
+const UNISWAP_V2_ROUTER_01: &[&str] = &[
+    "function factory() external pure returns (address)",
+    "function WETH() external pure returns (address)",
+    "function addLiquidity(address tokenA, address tokenB, uint amountADesired, uint amountBDesired, uint amountAMin, uint amountBMin, address to, uint deadline) external returns (uint amountA, uint amountB, uint liquidity)",
+    "function addLiquidityETH(address token, uint amountTokenDesired, uint amountTokenMin, uint amountETHMin, address to, uint deadline) external payable returns (uint amountToken, uint amountETH, uint liquidity)",
+    "function removeLiquidity(address tokenA, address tokenB, uint liquidity, uint amountAMin, uint amountBMin, address to, uint deadline) external returns (uint amountA, uint amountB)",
+    "function removeLiquidityETH(address token, uint liquidity, uint amountTokenMin, uint amountETHMin, address to, uint deadline) external returns (uint amountToken, uint amountETH)",
+    "function removeLiquidityWithPermit(address tokenA, address tokenB, uint liquidity, uint amountAMin, uint amountBMin, address to, uint deadline, bool approveMax, uint8 v, bytes32 r, bytes32 s) external returns (uint amountA, uint amountB)",
+    "function removeLiquidityETHWithPermit(address token, uint liquidity, uint amountTokenMin, uint amountETHMin, address to, uint deadline, bool approveMax, uint8 v, bytes32 r, bytes32 s) external returns (uint amountToken, uint amountETH)",
+    "function swapExactTokensForTokens(uint amountIn, uint amountOutMin, address[] calldata path, address to, uint deadline) external returns (uint[] memory amounts)",
+    "function swapTokensForExactTokens(uint amountOut, uint amountInMax, address[] calldata path, address to, uint deadline) external returns (uint[] memory amounts)",
+    "function swapExactETHForTokens(uint amountOutMin, address[] calldata path, address to, uint deadline) external payable returns (uint[] memory amounts)",
+    "function swapTokensForExactETH(uint amountOut, uint amountInMax, address[] calldata path, address to, uint deadline) external returns (uint[] memory amounts)",
+    "function swapExactTokensForETH(uint amountIn, uint amountOutMin, address[] calldata path, address to, uint deadline) external returns (uint[] memory amounts)",
+    "function swapETHForExactTokens(uint amountOut, address[] calldata path, address to, uint deadline) external payable returns (uint[] memory amounts)",
+    "function quote(uint amountA, uint reserveA, uint reserveB) external pure returns (uint amountB)",
+    "function getAmountOut(uint amountIn, uint reserveIn, uint reserveOut) external pure returns (uint amountOut)",
+    "function getAmountIn(uint amountOut, uint reserveIn, uint reserveOut) external pure returns (uint amountIn)",
+    "function getAmountsOut(uint amountIn, address[] calldata path) external view returns (uint[] memory amounts)",
+    "function getAmountsIn(uint amountOut, address[] calldata path) external view returns (uint[] memory amounts)",
+];
+
+/// Functions `IUniswapV2Router02` adds on top of `IUniswapV2Router01`
+const UNISWAP_V2_ROUTER_02: &[&str] = &[
+    "function removeLiquidityETHSupportingFeeOnTransferTokens(address token, uint liquidity, uint amountTokenMin, uint amountETHMin, address to, uint deadline) external returns (uint amountETH)",
+    "function removeLiquidityETHWithPermitSupportingFeeOnTransferTokens(address token, uint liquidity, uint amountTokenMin, uint amountETHMin, address to, uint deadline, bool approveMax, uint8 v, bytes32 r, bytes32 s) external returns (uint amountETH)",
+    "function swapExactTokensForTokensSupportingFeeOnTransferTokens(uint amountIn, uint amountOutMin, address[] calldata path, address to, uint deadline) external",
+    "function swapExactETHForTokensSupportingFeeOnTransferTokens(uint amountOutMin, address[] calldata path, address to, uint deadline) external payable",
+    "function swapExactTokensForETHSupportingFeeOnTransferTokens(uint amountIn, uint amountOutMin, address[] calldata path, address to, uint deadline) external",
+];
+
+const UNISWAP_V3_FACTORY: &[&str] = &[
+    "event PoolCreated(address indexed token0, address indexed token1, uint24 indexed fee, int24 tickSpacing, address pool)",
+    "function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)",
+    "function feeAmountTickSpacing(uint24 fee) external view returns (int24)",
+];
+
+const UNISWAP_V3_SWAP_ROUTER: &[&str] = &[
+    "struct ExactInputSingleParams { address tokenIn; address tokenOut; uint24 fee; address recipient; uint256 deadline; uint256 amountIn; uint256 amountOutMinimum; uint160 sqrtPriceLimitX96; }",
+    "struct ExactInputParams { bytes path; address recipient; uint256 deadline; uint256 amountIn; uint256 amountOutMinimum; }",
+    "struct ExactOutputSingleParams { address tokenIn; address tokenOut; uint24 fee; address recipient; uint256 deadline; uint256 amountOut; uint256 amountInMaximum; uint160 sqrtPriceLimitX96; }",
+    "struct ExactOutputParams { bytes path; address recipient; uint256 deadline; uint256 amountOut; uint256 amountInMaximum; }",
+    "function factory() external view returns (address)",
+    "function WETH9() external view returns (address)",
+    "function exactInputSingle(ExactInputSingleParams params) external payable returns (uint256 amountOut)",
+    "function exactInput(ExactInputParams params) external payable returns (uint256 amountOut)",
+    "function exactOutputSingle(ExactOutputSingleParams params) external payable returns (uint256 amountIn)",
+    "function exactOutput(ExactOutputParams params) external payable returns (uint256 amountIn)",
+    "function multicall(bytes[] data) external payable returns (bytes[] results)",
+    "function unwrapWETH9(uint256 amountMinimum, address recipient) external payable",
+    "function refundETH() external payable",
+    "function sweepToken(address token, uint256 amountMinimum, address recipient) external payable",
+];
+
+const UNISWAP_V3_SWAP_ROUTER_02: &[&str] = &[
+    "struct ExactInputSingleParams { address tokenIn; address tokenOut; uint24 fee; address recipient; uint256 amountIn; uint256 amountOutMinimum; uint160 sqrtPriceLimitX96; }",
+    "struct ExactInputParams { bytes path; address recipient; uint256 amountIn; uint256 amountOutMinimum; }",
+    "struct ExactOutputSingleParams { address tokenIn; address tokenOut; uint24 fee; address recipient; uint256 amountOut; uint256 amountInMaximum; uint160 sqrtPriceLimitX96; }",
+    "struct ExactOutputParams { bytes path; address recipient; uint256 amountOut; uint256 amountInMaximum; }",
+    "function factory() external view returns (address)",
+    "function factoryV2() external view returns (address)",
+    "function WETH9() external view returns (address)",
+    "function exactInputSingle(ExactInputSingleParams params) external payable returns (uint256 amountOut)",
+    "function exactInput(ExactInputParams params) external payable returns (uint256 amountOut)",
+    "function exactOutputSingle(ExactOutputSingleParams params) external payable returns (uint256 amountIn)",
+    "function exactOutput(ExactOutputParams params) external payable returns (uint256 amountIn)",
+    "function swapExactTokensForTokens(uint256 amountIn, uint256 amountOutMin, address[] path, address to) external payable returns (uint256 amountOut)",
+    "function swapTokensForExactTokens(uint256 amountOut, uint256 amountInMax, address[] path, address to) external payable returns (uint256 amountIn)",
+    "function multicall(bytes[] data) external payable returns (bytes[] results)",
+    "function multicall(uint256 deadline, bytes[] data) external payable returns (bytes[] results)",
+    "function multicall(bytes32 previousBlockhash, bytes[] data) external payable returns (bytes[] results)",
+    "function unwrapWETH9(uint256 amountMinimum, address recipient) external payable",
+    "function unwrapWETH9(uint256 amountMinimum) external payable",
+    "function refundETH() external payable",
+    "function sweepToken(address token, uint256 amountMinimum, address recipient) external payable",
+];
+
+const UNISWAP_UNIVERSAL_ROUTER: &[&str] = &[
+    "function execute(bytes commands, bytes[] inputs, uint256 deadline) external payable",
+    "function execute(bytes commands, bytes[] inputs) external payable",
+];
+
+/// Chains with the original Uniswap V2 deployment
+const V2_CHAINS: &[Chain] = &[Chain::Mainnet, Chain::Goerli];
+
+/// Chains with the original Uniswap V3 and Universal Router deployments
+const V3_CHAINS: &[Chain] = &[
+    Chain::Mainnet,
+    Chain::Goerli,
+    Chain::Optimism,
+    Chain::Arbitrum,
+    Chain::Polygon,
+];
+
+/// Signatures of a contract, one list per interface it implements
+type Signatures = &'static [&'static [&'static str]];
+
+/// Well-known Uniswap contracts, the chains they are deployed at that address
+/// on and the signatures the watcher relies on
+///
+/// Other chains reuse some of these addresses for different contracts, so a
+/// contract only matches on the chains listed
+const CONTRACTS: &[(&[Chain], &str, Signatures)] = &[
+    (
+        V2_CHAINS,
+        "0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f",
+        &[UNISWAP_V2_FACTORY],
+    ),
+    (
+        V2_CHAINS,
+        "0xf164fC0Ec4E93095b804a4795bBe1e041497b92a",
+        &[UNISWAP_V2_ROUTER_01],
+    ),
+    (
+        V2_CHAINS,
+        "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D",
+        &[UNISWAP_V2_ROUTER_01, UNISWAP_V2_ROUTER_02],
+    ),
+    (
+        V3_CHAINS,
+        "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+        &[UNISWAP_V3_FACTORY],
+    ),
+    (
+        V3_CHAINS,
+        "0xE592427A0AEce92De3Edee1F18E0157C05861564",
+        &[UNISWAP_V3_SWAP_ROUTER],
+    ),
+    (
+        V3_CHAINS,
+        "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45",
+        &[UNISWAP_V3_SWAP_ROUTER_02],
+    ),
+    (
+        V3_CHAINS,
+        "0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD",
+        &[UNISWAP_UNIVERSAL_ROUTER],
+    ),
+    (
+        V3_CHAINS,
+        "0xEf1c6E67703c7BD7107eed8303Fbe6EC2554BF6B",
+        &[UNISWAP_UNIVERSAL_ROUTER],
+    ),
+];
+
+/// ABIs of the well-known Uniswap contracts, compiled into the binary
+#[derive(Clone, Copy, Debug)]
+pub struct BuiltinSource;
+
+#[async_trait]
+impl AbiSource for BuiltinSource {
+    fn name(&self) -> &str {
+        "builtin"
+    }
+
+    async fn abi(&self, chain: Chain, address: Address) -> Result<Option<Abi>> {
+        for (chains, contract, signatures) in CONTRACTS {
+            if chains.contains(&chain) && contract.parse::<Address>()? == address {
+                return Ok(Some(parse_abi(&signatures.concat())?));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::{abi::Abi, addressbook::Chain, types::Address};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use super::AbiSource;
+use crate::config::AbiConfig;
+
+/// A cached ABI together with where and when it was fetched
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub chain_id: u64,
+    pub address: Address,
+    /// Name of the ABI source the entry was fetched from
+    pub source: String,
+    /// Unix timestamp of the fetch, in seconds
+    pub fetched_at: u64,
+    /// Implementation the contract pointed at when last seen, if it is a proxy
+    pub implementation: Option<Address>,
+    /// `None` for proxies whose ABI comes from a local source, which are only
+    /// cached to follow their implementation
+    pub abi: Option<Abi>,
+}
+
+impl CacheEntry {
+    pub fn new(chain: Chain, address: Address, source: &str, abi: Option<Abi>) -> Self {
+        Self {
+            chain_id: chain.into(),
+            address,
+            source: source.to_string(),
+            fetched_at: now(),
+            implementation: None,
+            abi,
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.fetched_at))
+    }
+}
+
+/// ABIs previously fetched from a remote source, stored as `{dir}/{chain id}/{address}.json`
+#[derive(Clone, Debug)]
+pub struct CacheSource {
+    dir: PathBuf,
+    /// Age after which entries are refetched, `None` to keep them forever
+    ttl: Option<Duration>,
+}
+
+impl CacheSource {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Option<Duration>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    pub fn from_config(config: &AbiConfig) -> Self {
+        let ttl = (config.cache_ttl > 0).then(|| Duration::from_secs(config.cache_ttl));
+        Self::new(config.cache_dir.clone(), ttl)
+    }
+
+    /// Returns the entry for `address` on `chain`, expired or not
+    pub fn get(&self, chain: Chain, address: Address) -> Result<Option<CacheEntry>> {
+        let file = self.file(chain.into(), address);
+        if !file.exists() {
+            return Ok(None);
+        }
+        let entry = std::fs::read_to_string(&file)?;
+        Ok(Some(serde_json::from_str(&entry).with_context(|| {
+            format!("Malformed cache entry {}", file.display())
+        })?))
+    }
+
+    /// Writes `entry` to a temporary file and moves it into place,
+    /// so readers never see a partially written entry
+    pub fn put(&self, entry: &CacheEntry) -> Result<()> {
+        let file = self.file(entry.chain_id, entry.address);
+        let dir = file.parent().context("Cache entry has no directory")?;
+        if !dir.exists() {
+            debug!("Creating cache directory {}", dir.display());
+            std::fs::create_dir_all(dir)?;
+        }
+
+        debug!("Caching ABI for {:?}", entry.address);
+        let tmp = file.with_extension(format!("json.{}.tmp", std::process::id()));
+        std::fs::write(&tmp, serde_json::to_string(entry)?)?;
+        std::fs::rename(&tmp, &file)?;
+        Ok(())
+    }
+
+    pub fn is_expired(&self, entry: &CacheEntry) -> bool {
+        self.ttl.is_some_and(|ttl| entry.age() > ttl)
+    }
+
+    /// Returns every readable entry, skipping malformed files
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries = vec![];
+        if !self.dir.exists() {
+            return Ok(entries);
+        }
+
+        for chain in std::fs::read_dir(&self.dir)? {
+            let chain = chain?.path();
+            if !chain.is_dir() {
+                continue;
+            }
+            for file in std::fs::read_dir(chain)? {
+                let file = file?.path();
+                if file
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+                {
+                    match std::fs::read_to_string(&file)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|entry| Ok(serde_json::from_str(&entry)?))
+                    {
+                        Ok(entry) => entries.push(entry),
+                        Err(e) => warn!("Skipping cache entry {}: {}", file.display(), e),
+                    }
+                }
+            }
+        }
+
+        entries.sort_by_key(|entry: &CacheEntry| (entry.chain_id, entry.address));
+        Ok(entries)
+    }
+
+    /// Removes expired entries, or every entry if `all` is set
+    /// Returns the number of entries removed
+    pub fn prune(&self, all: bool) -> Result<usize> {
+        let mut removed = 0;
+        for entry in self.entries()? {
+            if all || self.is_expired(&entry) {
+                std::fs::remove_file(self.file(entry.chain_id, entry.address))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn file(&self, chain_id: u64, address: Address) -> PathBuf {
+        self.dir
+            .join(chain_id.to_string())
+            .join(format!("{:?}.json", address))
+    }
+}
+
+#[async_trait]
+impl AbiSource for CacheSource {
+    fn name(&self) -> &str {
+        "cache"
+    }
+
+    async fn abi(&self, chain: Chain, address: Address) -> Result<Option<Abi>> {
+        Ok(self
+            .get(chain, address)?
+            .filter(|entry| !self.is_expired(entry))
+            .and_then(|entry| entry.abi))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
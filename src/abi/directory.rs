@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::{abi::Abi, addressbook::Chain, types::Address};
+
+use super::{read_abi, AbiSource};
+
+/// Vendored ABIs stored as `{dir}/{chain}/{address}.json`, or `{dir}/{address}.json`
+/// for ABIs shared by every chain
+#[derive(Clone, Debug)]
+pub struct DirectorySource {
+    dir: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl AbiSource for DirectorySource {
+    fn name(&self) -> &str {
+        "directory"
+    }
+
+    async fn abi(&self, chain: Chain, address: Address) -> Result<Option<Abi>> {
+        let file = format!("{:?}.json", address);
+        for path in [
+            self.dir.join(chain.as_ref()).join(&file),
+            self.dir.join(&file),
+        ] {
+            if path.exists() {
+                return read_abi(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::{abi::Abi, addressbook::Chain, etherscan::Client, types::Address};
+
+use super::AbiSource;
+
+/// Verified contract ABIs from the chain's Etherscan-style explorer
+#[derive(Clone, Debug)]
+pub struct EtherscanSource {
+    api_key: Option<String>,
+}
+
+impl EtherscanSource {
+    pub fn new(api_key: Option<String>) -> Self {
+        Self { api_key }
+    }
+}
+
+#[async_trait]
+impl AbiSource for EtherscanSource {
+    fn name(&self) -> &str {
+        "explorer"
+    }
+
+    async fn abi(&self, chain: Chain, address: Address) -> Result<Option<Abi>> {
+        let api_key = self
+            .api_key
+            .as_deref()
+            .with_context(|| format!("No explorer API key configured for {}", chain))?;
+        let etherscan = Client::new(chain, api_key)
+            .with_context(|| format!("Could not create explorer client for {}", chain))?;
+
+        Ok(Some(etherscan.contract_abi(address).await?))
+    }
+
+    fn remote(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,271 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use ethers::{
+    abi::Abi,
+    addressbook::Chain,
+    providers::{Provider, Ws},
+    types::Address,
+};
+use log::{debug, warn};
+use serde_json::Value;
+
+use crate::config::{AbiConfig, AbiSourceKind};
+
+mod builtin;
+mod cache;
+mod directory;
+mod etherscan;
+mod proxy;
+mod sourcify;
+
+pub use builtin::BuiltinSource;
+pub use cache::{CacheEntry, CacheSource};
+pub use directory::DirectorySource;
+pub use etherscan::EtherscanSource;
+pub use sourcify::SourcifySource;
+
+/// A place contract ABIs can be looked up from
+#[async_trait]
+pub trait AbiSource: Send + Sync {
+    /// Name of the source, used in logs
+    fn name(&self) -> &str;
+
+    /// Returns the ABI of `address` on `chain`, or `None` if the source does not know the contract
+    async fn abi(&self, chain: Chain, address: Address) -> Result<Option<Abi>>;
+
+    /// Whether the source goes over the network, making its ABIs worth caching
+    fn remote(&self) -> bool {
+        false
+    }
+}
+
+/// Looks up ABIs by trying each configured source in order
+pub struct AbiResolver {
+    sources: Vec<Box<dyn AbiSource>>,
+    cache: Option<CacheSource>,
+    /// Used to follow proxies to their implementation
+    provider: Option<Provider<Ws>>,
+}
+
+impl AbiResolver {
+    /// Builds the sources listed in `config`, using `api_key` for the chain's explorer
+    pub fn new(config: &AbiConfig, api_key: Option<String>) -> Self {
+        let cache = CacheSource::from_config(config);
+        let sources = config
+            .sources
+            .iter()
+            .map(|kind| -> Box<dyn AbiSource> {
+                match kind {
+                    AbiSourceKind::Cache => Box::new(cache.clone()),
+                    AbiSourceKind::Builtin => Box::new(BuiltinSource),
+                    AbiSourceKind::Directory => {
+                        Box::new(DirectorySource::new(config.directory.clone()))
+                    }
+                    AbiSourceKind::Sourcify => {
+                        Box::new(SourcifySource::new(config.sourcify.clone()))
+                    }
+                    AbiSourceKind::Explorer => Box::new(EtherscanSource::new(api_key.clone())),
+                }
+            })
+            .collect();
+
+        Self {
+            sources,
+            cache: config
+                .sources
+                .contains(&AbiSourceKind::Cache)
+                .then_some(cache),
+            provider: None,
+        }
+    }
+
+    /// Follows proxies through `provider` when resolving ABIs
+    pub fn with_provider(mut self, provider: Provider<Ws>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Returns the ABI of `address`, merged with its implementation's ABI if it is a proxy
+    ///
+    /// Proxy and implementation ABIs are looked up and cached under their own
+    /// addresses, so an upgrade is picked up as soon as the proxy points elsewhere
+    pub async fn resolve(&self, chain: Chain, address: Address) -> Result<Abi> {
+        let (abi, source) = self.lookup(chain, address).await?;
+        let Some(provider) = &self.provider else {
+            return Ok(abi);
+        };
+
+        match proxy::implementation(provider, address).await {
+            Ok(Some(implementation)) => {
+                debug!("{:?} is a proxy for {:?}", address, implementation);
+                if let Err(e) = self.record_implementation(chain, address, source, implementation) {
+                    warn!("Could not cache the implementation of {:?}: {}", address, e);
+                }
+                let (implementation, _) = self.lookup(chain, implementation).await?;
+                Ok(proxy::merge(abi, implementation))
+            }
+            Ok(None) => Ok(abi),
+            Err(e) => {
+                warn!("Could not check whether {:?} is a proxy: {}", address, e);
+                Ok(abi)
+            }
+        }
+    }
+
+    /// Returns the ABI of `address` and the name of the first source that knows it,
+    /// caching ABIs fetched over the network
+    async fn lookup(&self, chain: Chain, address: Address) -> Result<(Abi, &str)> {
+        for source in &self.sources {
+            match source.abi(chain, address).await {
+                Ok(Some(abi)) => {
+                    debug!("Using {} ABI for {:?}", source.name(), address);
+                    if let (true, Some(cache)) = (source.remote(), &self.cache) {
+                        let mut entry =
+                            CacheEntry::new(chain, address, source.name(), Some(abi.clone()));
+                        // Keep the implementation seen so far to notice upgrades
+                        entry.implementation = self
+                            .cache_entry(chain, address)
+                            .and_then(|entry| entry.implementation);
+                        cache.put(&entry)?;
+                    }
+                    return Ok((abi, source.name()));
+                }
+                Ok(None) => {}
+                Err(e) => warn!(
+                    "{} could not provide ABI for {:?}: {}",
+                    source.name(),
+                    address,
+                    e
+                ),
+            }
+        }
+
+        // An expired entry still beats not decoding at all
+        if let Some(abi) = self.cache_entry(chain, address).and_then(|entry| entry.abi) {
+            warn!("Using expired cached ABI for {:?}", address);
+            return Ok((abi, "cache"));
+        }
+
+        bail!("No ABI source knows {:?} on {}", address, chain)
+    }
+
+    /// Stores the implementation a proxy points at, logging upgrades
+    ///
+    /// Proxies whose ABI comes from `source`, a local one, get an entry without
+    /// an ABI, so that upgrades are noticed wherever the ABI comes from
+    fn record_implementation(
+        &self,
+        chain: Chain,
+        address: Address,
+        source: &str,
+        implementation: Address,
+    ) -> Result<()> {
+        let Some(cache) = &self.cache else {
+            return Ok(());
+        };
+        let mut entry = self
+            .cache_entry(chain, address)
+            .unwrap_or_else(|| CacheEntry::new(chain, address, source, None));
+        if entry.implementation == Some(implementation) {
+            return Ok(());
+        }
+
+        if let Some(previous) = entry.implementation {
+            log::info!(
+                "{:?} was upgraded from {:?} to {:?}",
+                address,
+                previous,
+                implementation
+            );
+        }
+        entry.implementation = Some(implementation);
+        cache.put(&entry)
+    }
+
+    /// Cached entry of `address`, a malformed one counting as a miss
+    fn cache_entry(&self, chain: Chain, address: Address) -> Option<CacheEntry> {
+        match self.cache.as_ref()?.get(chain, address) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Ignoring cache entry of {:?}: {:#}", address, e);
+                None
+            }
+        }
+    }
+}
+
+/// Reads a JSON ABI from a local file, either a bare ABI array
+/// or a compiler artifact with an `abi` field
+pub fn read_abi(path: &Path) -> Result<Abi> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read ABI {}", path.display()))?;
+    let value = match serde_json::from_str(&contents)? {
+        Value::Object(mut artifact) => artifact
+            .remove("abi")
+            .with_context(|| format!("{} has no abi field", path.display()))?,
+        value => value,
+    };
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Resolver trying a cache in a fresh directory, then the builtin ABIs
+    fn resolver(name: &str) -> (AbiResolver, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("abi-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = AbiConfig {
+            sources: vec![AbiSourceKind::Cache, AbiSourceKind::Builtin],
+            cache_dir: dir.clone(),
+            ..Default::default()
+        };
+        (AbiResolver::new(&config, None), dir)
+    }
+
+    #[test]
+    fn follows_implementations_of_local_abis() {
+        let (resolver, dir) = resolver("implementations");
+        let cache = CacheSource::new(dir, None);
+        let proxy = Address::repeat_byte(1);
+        for implementation in [Address::repeat_byte(2), Address::repeat_byte(3)] {
+            resolver
+                .record_implementation(Chain::Mainnet, proxy, "builtin", implementation)
+                .unwrap();
+            let entry = cache.get(Chain::Mainnet, proxy).unwrap().unwrap();
+            assert_eq!(entry.implementation, Some(implementation));
+            assert_eq!(entry.source, "builtin");
+            assert!(entry.abi.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn treats_malformed_entries_as_misses() {
+        let (resolver, dir) = resolver("malformed");
+        // Universal Router, known to the builtin source
+        let router: Address = "0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD"
+            .parse()
+            .unwrap();
+        std::fs::create_dir_all(dir.join("1")).unwrap();
+        std::fs::write(dir.join("1").join(format!("{:?}.json", router)), "{").unwrap();
+
+        let (abi, source) = resolver.lookup(Chain::Mainnet, router).await.unwrap();
+        assert_eq!(source, "builtin");
+        assert!(abi.function("execute").is_ok());
+        // The malformed entry is replaced
+        let implementation = Address::repeat_byte(2);
+        resolver
+            .record_implementation(Chain::Mainnet, router, source, implementation)
+            .unwrap();
+        let entry = CacheSource::new(dir, None)
+            .get(Chain::Mainnet, router)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.implementation, Some(implementation));
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use ethers::{
+    abi::Abi,
+    providers::Middleware,
+    types::{Address, Bytes, TransactionRequest, H256},
+};
+
+/// EIP-1967 `bytes32(uint256(keccak256("eip1967.proxy.implementation")) - 1)`
+const EIP1967_IMPLEMENTATION_SLOT: &str =
+    "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+/// EIP-1967 `bytes32(uint256(keccak256("eip1967.proxy.beacon")) - 1)`
+const EIP1967_BEACON_SLOT: &str =
+    "0xa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+/// EIP-1822 `keccak256("PROXIABLE")`
+const EIP1822_PROXIABLE_SLOT: &str =
+    "0xc5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7";
+/// Pre-EIP-1967 OpenZeppelin transparent proxies, `keccak256("org.zeppelinos.proxy.implementation")`
+const ZEPPELINOS_IMPLEMENTATION_SLOT: &str =
+    "0x7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3";
+
+/// Selector of the beacon's `implementation()`
+const IMPLEMENTATION_SELECTOR: [u8; 4] = [0x5c, 0x60, 0xda, 0x1b];
+
+/// Reads the implementation address of an EIP-1967, EIP-1822 or transparent proxy
+/// Returns `None` if `address` does not look like a proxy
+pub async fn implementation<M: Middleware>(
+    provider: &M,
+    address: Address,
+) -> Result<Option<Address>>
+where
+    M::Error: 'static,
+{
+    for slot in [
+        EIP1967_IMPLEMENTATION_SLOT,
+        EIP1822_PROXIABLE_SLOT,
+        ZEPPELINOS_IMPLEMENTATION_SLOT,
+    ] {
+        if let Some(implementation) = read_address_slot(provider, address, slot).await? {
+            return Ok(Some(implementation));
+        }
+    }
+
+    // Beacon proxies delegate to whatever the beacon currently points at
+    if let Some(beacon) = read_address_slot(provider, address, EIP1967_BEACON_SLOT).await? {
+        let call = TransactionRequest::new()
+            .to(beacon)
+            .data(Bytes::from(IMPLEMENTATION_SELECTOR.to_vec()));
+        let output = provider.call(&call.into(), None).await?;
+        if output.len() == 32 {
+            return Ok(non_zero(Address::from_slice(&output[12..])));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Merges a proxy's own ABI into its implementation's, keeping the
+/// implementation's definition wherever both declare the same signature
+pub fn merge(proxy: Abi, mut implementation: Abi) -> Abi {
+    for function in proxy.functions.into_values().flatten() {
+        let overloads = implementation
+            .functions
+            .entry(function.name.clone())
+            .or_default();
+        if !overloads
+            .iter()
+            .any(|existing| existing.short_signature() == function.short_signature())
+        {
+            overloads.push(function);
+        }
+    }
+
+    for event in proxy.events.into_values().flatten() {
+        let overloads = implementation.events.entry(event.name.clone()).or_default();
+        if !overloads
+            .iter()
+            .any(|existing| existing.signature() == event.signature())
+        {
+            overloads.push(event);
+        }
+    }
+
+    for error in proxy.errors.into_values().flatten() {
+        let overloads = implementation.errors.entry(error.name.clone()).or_default();
+        if !overloads
+            .iter()
+            .any(|existing| existing.signature() == error.signature())
+        {
+            overloads.push(error);
+        }
+    }
+
+    implementation.receive |= proxy.receive;
+    implementation.fallback |= proxy.fallback;
+    implementation
+}
+
+async fn read_address_slot<M: Middleware>(
+    provider: &M,
+    address: Address,
+    slot: &str,
+) -> Result<Option<Address>>
+where
+    M::Error: 'static,
+{
+    let slot: H256 = slot.parse()?;
+    let value = provider.get_storage_at(address, slot, None).await?;
+    Ok(non_zero(Address::from(value)))
+}
+
+fn non_zero(address: Address) -> Option<Address> {
+    (!address.is_zero()).then_some(address)
+}
@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::{abi::Abi, addressbook::Chain, types::Address, utils::to_checksum};
+use serde_json::Value;
+
+use super::AbiSource;
+
+/// Verified contracts from a Sourcify repository, served over HTTP or mirrored on disk
+///
+/// Both use the repository layout
+/// `contracts/{full_match,partial_match}/{chain id}/{checksum address}/metadata.json`
+#[derive(Clone, Debug)]
+pub struct SourcifySource {
+    base: String,
+}
+
+impl SourcifySource {
+    /// `base` is either a repository URL such as `https://repo.sourcify.dev` or a local directory
+    pub fn new(base: impl Into<String>) -> Self {
+        Self { base: base.into() }
+    }
+
+    async fn metadata(&self, path: &str) -> Result<Option<Value>> {
+        if !self.remote() {
+            let path = Path::new(&self.base).join(path);
+            if !path.exists() {
+                return Ok(None);
+            }
+            return Ok(Some(serde_json::from_str(&std::fs::read_to_string(path)?)?));
+        }
+
+        let url = format!("{}/{}", self.base.trim_end_matches('/'), path);
+        let response = reqwest::get(&url).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+}
+
+#[async_trait]
+impl AbiSource for SourcifySource {
+    fn name(&self) -> &str {
+        "sourcify"
+    }
+
+    async fn abi(&self, chain: Chain, address: Address) -> Result<Option<Abi>> {
+        let address = to_checksum(&address, None);
+        for matched in ["full_match", "partial_match"] {
+            let path = format!(
+                "contracts/{}/{}/{}/metadata.json",
+                matched,
+                u64::from(chain),
+                address
+            );
+            if let Some(mut metadata) = self.metadata(&path).await? {
+                let abi = metadata
+                    .pointer_mut("/output/abi")
+                    .map(Value::take)
+                    .context("Sourcify metadata has no ABI")?;
+                return Ok(Some(serde_json::from_value(abi)?));
+            }
+        }
+        Ok(None)
+    }
+
+    fn remote(&self) -> bool {
+        self.base.starts_with("http://") || self.base.starts_with("https://")
+    }
+}
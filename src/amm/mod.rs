@@ -0,0 +1,2 @@
+pub mod v2;
+pub mod v3;
@@ -0,0 +1,307 @@
+use ethers::types::{Address, U256, U512};
+
+use crate::model::{Reserves, SwapIntent, SwapKind, SwapQuote};
+
+/// Basis points in a whole
+const BPS: u64 = 10_000;
+/// Fixed point scale of the price ratios multiplied along a path
+const SCALE: u64 = 1_000_000_000_000_000_000;
+
+/// Output of swapping `amount_in` against a pair, as `UniswapV2Library.getAmountOut`.
+/// `None` where the library would revert, or on overflow
+pub fn get_amount_out(amount_in: U256, reserve_in: U256, reserve_out: U256) -> Option<U256> {
+    if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+        return None;
+    }
+    let amount_in_with_fee = amount_in.checked_mul(997.into())?;
+    let numerator = amount_in_with_fee.checked_mul(reserve_out)?;
+    let denominator = reserve_in
+        .checked_mul(1000.into())?
+        .checked_add(amount_in_with_fee)?;
+    Some(numerator / denominator)
+}
+
+/// Input needed to get `amount_out` from a pair, as `UniswapV2Library.getAmountIn`.
+/// `None` where the library would revert, or on overflow
+pub fn get_amount_in(amount_out: U256, reserve_in: U256, reserve_out: U256) -> Option<U256> {
+    if amount_out.is_zero() || reserve_in.is_zero() || amount_out >= reserve_out {
+        return None;
+    }
+    let numerator = reserve_in
+        .checked_mul(amount_out)?
+        .checked_mul(1000.into())?;
+    let denominator = (reserve_out - amount_out).checked_mul(997.into())?;
+    (numerator / denominator).checked_add(1.into())
+}
+
+/// Amounts along a path for an exact input, as `UniswapV2Library.getAmountsOut`.
+/// `reserves` holds the input and output reserves of each hop
+pub fn get_amounts_out(amount_in: U256, reserves: &[(U256, U256)]) -> Option<Vec<U256>> {
+    let mut amounts = vec![amount_in];
+    for &(reserve_in, reserve_out) in reserves {
+        amounts.push(get_amount_out(*amounts.last()?, reserve_in, reserve_out)?);
+    }
+    Some(amounts)
+}
+
+/// Amounts along a path for an exact output, as `UniswapV2Library.getAmountsIn`
+pub fn get_amounts_in(amount_out: U256, reserves: &[(U256, U256)]) -> Option<Vec<U256>> {
+    let mut amounts = vec![amount_out];
+    for &(reserve_in, reserve_out) in reserves.iter().rev() {
+        amounts.push(get_amount_in(*amounts.last()?, reserve_in, reserve_out)?);
+    }
+    amounts.reverse();
+    Some(amounts)
+}
+
+/// Input and output reserves of each hop of `path`, given the reserves of its
+/// pairs, which hold tokens sorted by address
+pub fn hop_reserves(path: &[Address], reserves: &[Reserves]) -> Option<Vec<(U256, U256)>> {
+    if path.len() != reserves.len() + 1 {
+        return None;
+    }
+    Some(
+        path.windows(2)
+            .zip(reserves)
+            .map(|(hop, reserves)| {
+                if hop[0] < hop[1] {
+                    (reserves.reserve0, reserves.reserve1)
+                } else {
+                    (reserves.reserve1, reserves.reserve0)
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Expected execution of a V2 swap against the reserves attached to it
+///
+/// Fee-on-transfer tokens deliver less than the quote to each pair, so their
+/// swaps do worse than quoted
+pub fn quote(swap: &SwapIntent) -> Option<SwapQuote> {
+    if swap.version != 2 {
+        return None;
+    }
+    let hops = hop_reserves(&swap.path, &swap.reserves)?;
+    let amounts = match swap.kind {
+        SwapKind::ExactIn => get_amounts_out(swap.amount_in?, &hops)?,
+        SwapKind::ExactOut => get_amounts_in(swap.amount_out, &hops)?,
+    };
+    if amounts.iter().any(U256::is_zero) {
+        return None;
+    }
+
+    // Execution price over the price before the swap, per hop and along the path
+    let mut ratio = U256::from(SCALE);
+    let mut hop_impact_bps = Vec::with_capacity(hops.len());
+    for (amount, &(reserve_in, reserve_out)) in amounts.windows(2).zip(&hops) {
+        let hop_ratio =
+            amount[1].full_mul(reserve_in) * U512::from(SCALE) / amount[0].full_mul(reserve_out);
+        let hop_ratio = U256::try_from(hop_ratio).ok()?;
+        hop_impact_bps.push(impact_bps(hop_ratio));
+        ratio = ratio.checked_mul(hop_ratio)? / SCALE;
+    }
+
+    let (quoted, limit) = match swap.kind {
+        SwapKind::ExactIn => (amounts[amounts.len() - 1], swap.amount_out),
+        SwapKind::ExactOut => (amounts[0], swap.amount_in?),
+    };
+    let slippage_bps = match swap.kind {
+        SwapKind::ExactIn => signed_bps(quoted, limit, quoted),
+        SwapKind::ExactOut => signed_bps(limit, quoted, quoted),
+    };
+
+    Some(SwapQuote {
+        amounts,
+        hop_impact_bps,
+        price_impact_bps: impact_bps(ratio),
+        slippage_bps,
+    })
+}
+
+/// Shortfall of a `SCALE` fixed point price ratio from 1, in basis points
+fn impact_bps(ratio: U256) -> u32 {
+    let scale = U256::from(SCALE);
+    if ratio >= scale {
+        return 0;
+    }
+    ((scale - ratio) * BPS / scale).as_u32()
+}
+
+/// `(a - b) / of` in basis points, saturating at the bounds of `i64`
+fn signed_bps(a: U256, b: U256, of: U256) -> i64 {
+    let (difference, negative) = if a >= b {
+        (a - b, false)
+    } else {
+        (b - a, true)
+    };
+    let bps = difference.saturating_mul(BPS.into()) / of;
+    let bps = if bps > U256::from(i64::MAX) {
+        i64::MAX
+    } else {
+        bps.as_u64() as i64
+    };
+    if negative {
+        -bps
+    } else {
+        bps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SwapDirection;
+
+    /// Tokens sorted by address, `A` < `B` < `C`
+    const A: u64 = 1;
+    const B: u64 = 2;
+    const C: u64 = 3;
+
+    fn token(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn e(decimals: usize) -> U256 {
+        U256::exp10(decimals)
+    }
+
+    /// 100 A against 200,000 B, B having 6 decimals
+    fn ab() -> Reserves {
+        Reserves {
+            reserve0: e(18) * 100,
+            reserve1: e(6) * 200_000,
+            block_number: 1,
+        }
+    }
+
+    /// 500,000 B against 250 C
+    fn bc() -> Reserves {
+        Reserves {
+            reserve0: e(6) * 500_000,
+            reserve1: e(18) * 250,
+            block_number: 1,
+        }
+    }
+
+    fn swap(kind: SwapKind, path: &[u64], amount_in: U256, amount_out: U256) -> SwapIntent {
+        let path: Vec<_> = path.iter().copied().map(token).collect();
+        let (pools, reserves) = path
+            .windows(2)
+            .map(|hop| {
+                if hop.contains(&token(A)) {
+                    (token(0xab), ab())
+                } else {
+                    (token(0xbc), bc())
+                }
+            })
+            .unzip();
+        let function = match kind {
+            SwapKind::ExactIn => "swapExactTokensForTokens",
+            SwapKind::ExactOut => "swapTokensForExactTokens",
+        };
+        SwapIntent {
+            function: function.to_string(),
+            kind,
+            direction: SwapDirection::TokenToToken,
+            native_eth: false,
+            path,
+            amount_in: Some(amount_in),
+            amount_out,
+            recipient: None,
+            deadline: None,
+            fee_on_transfer: false,
+            version: 2,
+            fees: vec![],
+            pools,
+            reserves,
+            quote: None,
+        }
+    }
+
+    #[test]
+    fn matches_the_library() {
+        let (ab, bc) = (ab(), bc());
+        assert_eq!(
+            get_amount_out(e(18), ab.reserve0, ab.reserve1),
+            Some(1_974_316_068.into())
+        );
+        assert_eq!(
+            get_amount_in(1_974_316_068.into(), ab.reserve0, ab.reserve1),
+            Some(U256::from(999_999_999_593_763_120u64))
+        );
+        assert_eq!(get_amount_out(U256::zero(), ab.reserve0, ab.reserve1), None);
+        assert_eq!(get_amount_in(ab.reserve1, ab.reserve0, ab.reserve1), None);
+
+        let hops = [(ab.reserve0, ab.reserve1), (bc.reserve0, bc.reserve1)];
+        assert_eq!(
+            get_amounts_out(e(18), &hops),
+            Some(vec![
+                e(18),
+                1_974_316_068.into(),
+                U256::from(980_337_181_969_860_654u64)
+            ])
+        );
+    }
+
+    #[test]
+    fn orients_reserves_along_the_path() {
+        let path = [token(C), token(B), token(A)];
+        let hops = hop_reserves(&path, &[bc(), ab()]).unwrap();
+        assert_eq!(
+            hops,
+            vec![
+                (bc().reserve1, bc().reserve0),
+                (ab().reserve1, ab().reserve0)
+            ]
+        );
+        assert_eq!(
+            get_amounts_in(e(18), &hops),
+            Some(vec![
+                U256::from(1_020_323_924_178_735_329u64),
+                2_026_280_863.into(),
+                e(18)
+            ])
+        );
+        assert_eq!(hop_reserves(&path, &[bc()]), None);
+    }
+
+    #[test]
+    fn quotes_exact_input() {
+        let minimum = U256::from(970_533_810_150_162_048u64);
+        let quote = quote(&swap(SwapKind::ExactIn, &[A, B, C], e(18), minimum)).unwrap();
+        assert_eq!(quote.amounts[2], U256::from(980_337_181_969_860_654u64));
+        assert_eq!(quote.hop_impact_bps, vec![128, 69]);
+        assert_eq!(quote.price_impact_bps, 196);
+        assert_eq!(quote.slippage_bps, 99);
+    }
+
+    #[test]
+    fn quotes_exact_output() {
+        let maximum = U256::from(1_030_000_000_000_000_000u64);
+        let quote = quote(&swap(SwapKind::ExactOut, &[C, B, A], maximum, e(18))).unwrap();
+        assert_eq!(quote.amounts[0], U256::from(1_020_323_924_178_735_329u64));
+        assert_eq!(quote.amounts[2], e(18));
+        // Room left below the maximum, in basis points of the quoted input
+        assert_eq!(quote.slippage_bps, 94);
+    }
+
+    #[test]
+    fn reports_swaps_that_would_revert() {
+        let minimum = U256::from(990_140_553_789_559_260u64);
+        let exact_in = quote(&swap(SwapKind::ExactIn, &[A, B, C], e(18), minimum)).unwrap();
+        assert_eq!(exact_in.slippage_bps, -99);
+
+        let maximum = U256::from(1_010_000_000_000_000_000u64);
+        let exact_out = quote(&swap(SwapKind::ExactOut, &[C, B, A], maximum, e(18))).unwrap();
+        assert_eq!(exact_out.slippage_bps, -101);
+    }
+
+    #[test]
+    fn saturates_basis_points() {
+        assert_eq!(signed_bps(U256::MAX, U256::zero(), U256::one()), i64::MAX);
+        assert_eq!(signed_bps(U256::zero(), U256::MAX, U256::one()), -i64::MAX);
+        assert_eq!(impact_bps(U256::from(SCALE) * 2), 0);
+        assert_eq!(impact_bps(U256::zero()), 10_000);
+    }
+}
@@ -0,0 +1,459 @@
+use ethers::types::{I256, U256, U512};
+
+/// Lowest tick a pool can reach, as `TickMath.MIN_TICK`
+pub const MIN_TICK: i32 = -887272;
+/// Highest tick a pool can reach, as `TickMath.MAX_TICK`
+pub const MAX_TICK: i32 = 887272;
+/// Fee denominator, fees being in hundredths of a basis point
+pub const FEE_PIPS: u32 = 1_000_000;
+
+/// `sqrt_ratio_at_tick(MIN_TICK)`
+pub fn min_sqrt_ratio() -> U256 {
+    U256::from(4295128739u64)
+}
+
+/// `sqrt_ratio_at_tick(MAX_TICK)`
+pub fn max_sqrt_ratio() -> U256 {
+    U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap()
+}
+
+/// 2^96, the scale of `sqrtPriceX96`
+fn q96() -> U256 {
+    U256::one() << 96
+}
+
+/// Largest value of a `uint160`, which sqrt prices are stored as
+fn max_u160() -> U256 {
+    (U256::one() << 160) - 1
+}
+
+/// `a * b / denominator` rounded down without intermediate overflow, as `FullMath.mulDiv`
+pub fn mul_div(a: U256, b: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    U256::try_from(a.full_mul(b) / U512::from(denominator)).ok()
+}
+
+/// `a * b / denominator` rounded up, as `FullMath.mulDivRoundingUp`
+pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> Option<U256> {
+    let result = mul_div(a, b, denominator)?;
+    if (a.full_mul(b) % U512::from(denominator)).is_zero() {
+        Some(result)
+    } else {
+        result.checked_add(U256::one())
+    }
+}
+
+/// `a / b` rounded up, as `UnsafeMath.divRoundingUp`
+fn div_rounding_up(a: U256, b: U256) -> Option<U256> {
+    if b.is_zero() {
+        return None;
+    }
+    let (quotient, remainder) = a.div_mod(b);
+    Some(quotient + U256::from(!remainder.is_zero() as u8))
+}
+
+/// Sqrt price of `tick` as a Q64.96, as `TickMath.getSqrtRatioAtTick`
+pub fn sqrt_ratio_at_tick(tick: i32) -> Option<U256> {
+    const FACTORS: [&str; 19] = [
+        "fff97272373d413259a46990580e213a",
+        "fff2e50f5f656932ef12357cf3c7fdcc",
+        "ffe5caca7e10e4e61c3624eaa0941cd0",
+        "ffcb9843d60f6159c9db58835c926644",
+        "ff973b41fa98c081472e6896dfb254c0",
+        "ff2ea16466c96a3843ec78b326b52861",
+        "fe5dee046a99a2a811c461f1969c3053",
+        "fcbe86c7900a88aedcffc83b479aa3a4",
+        "f987a7253ac413176f2b074cf7815e54",
+        "f3392b0822b70005940c7a398e4b70f3",
+        "e7159475a2c29b7443b29c7fa6e889d9",
+        "d097f3bdfd2022b8845ad8f792aa5825",
+        "a9f746462d870fdf8a65dc1f90e061e5",
+        "70d869a156d2a1b890bb3df62baf32f7",
+        "31be135f97d08fd981231505542fcfa6",
+        "9aa508b5b7a84e1c677de54f3e99bc9",
+        "5d6af8dedb81196699c329225ee604",
+        "2216e584f5fa1ea926041bedfe98",
+        "48a170391f7dc42444e8fa2",
+    ];
+
+    let abs_tick = tick.unsigned_abs();
+    if abs_tick > MAX_TICK as u32 {
+        return None;
+    }
+    let mut ratio = if abs_tick & 1 != 0 {
+        U256::from_str_radix("fffcb933bd6fad37aa2d162d1a594001", 16).ok()?
+    } else {
+        U256::one() << 128
+    };
+    for (bit, factor) in FACTORS.iter().enumerate() {
+        if abs_tick & (2 << bit) != 0 {
+            ratio = (ratio * U256::from_str_radix(factor, 16).ok()?) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // Back from Q128.128 to Q64.96, rounding up so that the tick of the result is `tick`
+    let rounding = (ratio.low_u64() & 0xffff_ffff != 0) as u8;
+    Some((ratio >> 32) + U256::from(rounding))
+}
+
+/// Greatest tick whose sqrt price is at most `sqrt_price_x96`, as `TickMath.getTickAtSqrtRatio`
+pub fn tick_at_sqrt_ratio(sqrt_price_x96: U256) -> Option<i32> {
+    if sqrt_price_x96 < min_sqrt_ratio() || sqrt_price_x96 >= max_sqrt_ratio() {
+        return None;
+    }
+    let ratio = sqrt_price_x96 << 32;
+
+    // Integer part of log2, then 14 bits of fraction by repeated squaring
+    let msb = ratio.bits() - 1;
+    let mut r = if msb >= 128 {
+        ratio >> (msb - 127)
+    } else {
+        ratio << (127 - msb)
+    };
+    let mut log_2 = I256::from(msb as i64 - 128) * I256::from_raw(U256::one() << 64);
+    for bit in (50..64).rev() {
+        r = (r * r) >> 127;
+        let f = r >> 128;
+        log_2 = I256::from_raw(log_2.into_raw() | (f << bit));
+        r >>= f.as_usize();
+    }
+
+    // Change of base to sqrt(1.0001), bounded by the error of the approximation
+    let log_sqrt10001 = log_2 * I256::from_dec_str("255738958999603826347141").ok()?;
+    let tick_low = (log_sqrt10001
+        - I256::from_dec_str("3402992956809132418596140100660247210").ok()?)
+    .asr(128)
+    .as_i32();
+    let tick_high = (log_sqrt10001
+        + I256::from_dec_str("291339464771989622907027621153398088495").ok()?)
+    .asr(128)
+    .as_i32();
+
+    Some(if tick_low == tick_high {
+        tick_low
+    } else if sqrt_ratio_at_tick(tick_high)? <= sqrt_price_x96 {
+        tick_high
+    } else {
+        tick_low
+    })
+}
+
+/// Amount of token0 between two sqrt prices, as `SqrtPriceMath.getAmount0Delta`
+pub fn amount0_delta(a: U256, b: U256, liquidity: u128, round_up: bool) -> Option<U256> {
+    let (a, b) = if a > b { (b, a) } else { (a, b) };
+    if a.is_zero() {
+        return None;
+    }
+    let numerator1 = U256::from(liquidity) << 96;
+    let numerator2 = b - a;
+    if round_up {
+        div_rounding_up(mul_div_rounding_up(numerator1, numerator2, b)?, a)
+    } else {
+        Some(mul_div(numerator1, numerator2, b)? / a)
+    }
+}
+
+/// Amount of token1 between two sqrt prices, as `SqrtPriceMath.getAmount1Delta`
+pub fn amount1_delta(a: U256, b: U256, liquidity: u128, round_up: bool) -> Option<U256> {
+    let (a, b) = if a > b { (b, a) } else { (a, b) };
+    if round_up {
+        mul_div_rounding_up(U256::from(liquidity), b - a, q96())
+    } else {
+        mul_div(U256::from(liquidity), b - a, q96())
+    }
+}
+
+/// Sqrt price after adding `amount` of token0, as
+/// `SqrtPriceMath.getNextSqrtPriceFromAmount0RoundingUp` with `add` set
+fn next_sqrt_price_from_amount0(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount: U256,
+) -> Option<U256> {
+    if amount.is_zero() {
+        return Some(sqrt_price_x96);
+    }
+    let numerator1 = U256::from(liquidity) << 96;
+    if let Some(product) = amount.checked_mul(sqrt_price_x96) {
+        if let Some(denominator) = numerator1.checked_add(product) {
+            return mul_div_rounding_up(numerator1, sqrt_price_x96, denominator);
+        }
+    }
+    div_rounding_up(
+        numerator1,
+        (numerator1 / sqrt_price_x96).checked_add(amount)?,
+    )
+}
+
+/// Sqrt price after adding `amount` of token1, as
+/// `SqrtPriceMath.getNextSqrtPriceFromAmount1RoundingDown` with `add` set
+fn next_sqrt_price_from_amount1(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount: U256,
+) -> Option<U256> {
+    let quotient = if amount <= max_u160() {
+        (amount << 96) / U256::from(liquidity)
+    } else {
+        mul_div(amount, q96(), U256::from(liquidity))?
+    };
+    sqrt_price_x96
+        .checked_add(quotient)
+        .filter(|price| *price <= max_u160())
+}
+
+/// Sqrt price after swapping `amount_in` into the pool, as
+/// `SqrtPriceMath.getNextSqrtPriceFromInput`
+pub fn next_sqrt_price_from_input(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount_in: U256,
+    zero_for_one: bool,
+) -> Option<U256> {
+    if sqrt_price_x96.is_zero() || liquidity == 0 {
+        return None;
+    }
+    if zero_for_one {
+        next_sqrt_price_from_amount0(sqrt_price_x96, liquidity, amount_in)
+    } else {
+        next_sqrt_price_from_amount1(sqrt_price_x96, liquidity, amount_in)
+    }
+}
+
+/// Outcome of swapping within a single tick range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next_x96: U256,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub fee_amount: U256,
+}
+
+/// Swaps up to `amount_remaining` of exact input from `sqrt_price_current_x96`
+/// towards `sqrt_price_target_x96`, as `SwapMath.computeSwapStep` for a
+/// positive amount. `fee_pips` is in hundredths of a basis point
+pub fn compute_swap_step(
+    sqrt_price_current_x96: U256,
+    sqrt_price_target_x96: U256,
+    liquidity: u128,
+    amount_remaining: U256,
+    fee_pips: u32,
+) -> Option<SwapStep> {
+    let zero_for_one = sqrt_price_current_x96 >= sqrt_price_target_x96;
+    let fee_pips = U256::from(fee_pips);
+    let fee_denominator = U256::from(FEE_PIPS);
+
+    let amount_remaining_less_fee = mul_div(
+        amount_remaining,
+        fee_denominator - fee_pips,
+        fee_denominator,
+    )?;
+    let amount_to_target = if zero_for_one {
+        amount0_delta(
+            sqrt_price_target_x96,
+            sqrt_price_current_x96,
+            liquidity,
+            true,
+        )?
+    } else {
+        amount1_delta(
+            sqrt_price_current_x96,
+            sqrt_price_target_x96,
+            liquidity,
+            true,
+        )?
+    };
+    let sqrt_price_next_x96 = if amount_remaining_less_fee >= amount_to_target {
+        sqrt_price_target_x96
+    } else {
+        next_sqrt_price_from_input(
+            sqrt_price_current_x96,
+            liquidity,
+            amount_remaining_less_fee,
+            zero_for_one,
+        )?
+    };
+
+    let reached_target = sqrt_price_next_x96 == sqrt_price_target_x96;
+    let (amount_in, amount_out) = if zero_for_one {
+        (
+            if reached_target {
+                amount_to_target
+            } else {
+                amount0_delta(sqrt_price_next_x96, sqrt_price_current_x96, liquidity, true)?
+            },
+            amount1_delta(
+                sqrt_price_next_x96,
+                sqrt_price_current_x96,
+                liquidity,
+                false,
+            )?,
+        )
+    } else {
+        (
+            if reached_target {
+                amount_to_target
+            } else {
+                amount1_delta(sqrt_price_current_x96, sqrt_price_next_x96, liquidity, true)?
+            },
+            amount0_delta(
+                sqrt_price_current_x96,
+                sqrt_price_next_x96,
+                liquidity,
+                false,
+            )?,
+        )
+    };
+
+    // Short of the target, whatever input is left over goes to the fee
+    let fee_amount = if reached_target {
+        mul_div_rounding_up(amount_in, fee_pips, fee_denominator - fee_pips)?
+    } else {
+        amount_remaining - amount_in
+    };
+
+    Some(SwapStep {
+        sqrt_price_next_x96,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    #[test]
+    fn maps_ticks_to_prices() {
+        assert_eq!(sqrt_ratio_at_tick(MIN_TICK), Some(min_sqrt_ratio()));
+        assert_eq!(sqrt_ratio_at_tick(MAX_TICK), Some(max_sqrt_ratio()));
+        assert_eq!(sqrt_ratio_at_tick(0), Some(q96()));
+        assert_eq!(sqrt_ratio_at_tick(MIN_TICK + 1), Some(n("4295343490")));
+        assert_eq!(
+            sqrt_ratio_at_tick(MAX_TICK - 1),
+            Some(n("1461373636630004318706518188784493106690254656249"))
+        );
+        assert_eq!(sqrt_ratio_at_tick(MIN_TICK - 1), None);
+        assert_eq!(sqrt_ratio_at_tick(MAX_TICK + 1), None);
+
+        // Every bit of the tick against sqrt(1.0001^tick) in floating point
+        for tick in (0..20).map(|bit| 1 << bit).filter(|tick| *tick <= MAX_TICK) {
+            for tick in [tick, -tick] {
+                let expected = 1.0001f64.powf(tick as f64 / 2.0) * 2f64.powi(96);
+                let actual = sqrt_ratio_at_tick(tick)
+                    .unwrap()
+                    .to_string()
+                    .parse::<f64>()
+                    .unwrap();
+                assert!(
+                    ((actual - expected) / expected).abs() < 1e-9,
+                    "tick {}",
+                    tick
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn maps_prices_to_ticks() {
+        assert_eq!(tick_at_sqrt_ratio(min_sqrt_ratio()), Some(MIN_TICK));
+        assert_eq!(tick_at_sqrt_ratio(max_sqrt_ratio() - 1), Some(MAX_TICK - 1));
+        assert_eq!(tick_at_sqrt_ratio(min_sqrt_ratio() - 1), None);
+        assert_eq!(tick_at_sqrt_ratio(max_sqrt_ratio()), None);
+        for tick in [
+            MIN_TICK + 1,
+            -500000,
+            -60,
+            -1,
+            0,
+            1,
+            60,
+            12345,
+            MAX_TICK - 1,
+        ] {
+            let price = sqrt_ratio_at_tick(tick).unwrap();
+            assert_eq!(tick_at_sqrt_ratio(price), Some(tick));
+            assert_eq!(tick_at_sqrt_ratio(price - 1), Some(tick - 1));
+        }
+    }
+
+    // Vectors from the v3-core SwapMath spec
+    #[test]
+    fn computes_swap_steps() {
+        let liquidity = 2_000_000_000_000_000_000;
+        let amount = n("1000000000000000000");
+
+        // Capped at the target price, sqrt(101/100)
+        let target = n("79623317895830914510639640423");
+        assert_eq!(
+            compute_swap_step(q96(), target, liquidity, amount, 600),
+            Some(SwapStep {
+                sqrt_price_next_x96: target,
+                amount_in: n("9975124224178055"),
+                amount_out: n("9925619580021728"),
+                fee_amount: n("5988667735148"),
+            })
+        );
+
+        // Fully spent short of the target price, sqrt(1000/100)
+        let step = compute_swap_step(
+            q96(),
+            n("250541448375047931186413801569"),
+            liquidity,
+            amount,
+            600,
+        )
+        .unwrap();
+        assert_eq!(step.amount_in, n("999400000000000000"));
+        assert_eq!(step.amount_out, n("666399946655997866"));
+        assert_eq!(step.fee_amount, n("600000000000000"));
+        assert_eq!(
+            step.sqrt_price_next_x96,
+            next_sqrt_price_from_input(q96(), liquidity, n("999400000000000000"), false).unwrap()
+        );
+
+        // Target price of 1 with a partial input
+        assert_eq!(
+            compute_swap_step(
+                n("2"),
+                n("1"),
+                1,
+                n("3915081100057732413702495386755767"),
+                1
+            ),
+            Some(SwapStep {
+                sqrt_price_next_x96: n("1"),
+                amount_in: n("39614081257132168796771975168"),
+                amount_out: U256::zero(),
+                fee_amount: n("39614120871253040049813"),
+            })
+        );
+
+        // Entire input taken as fee
+        assert_eq!(
+            compute_swap_step(
+                n("2413"),
+                n("79887613182836312"),
+                1985041575832132834610021537970,
+                n("10"),
+                1872
+            ),
+            Some(SwapStep {
+                sqrt_price_next_x96: n("2413"),
+                amount_in: U256::zero(),
+                amount_out: U256::zero(),
+                fee_amount: n("10"),
+            })
+        );
+    }
+}
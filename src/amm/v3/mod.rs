@@ -0,0 +1,249 @@
+mod math;
+mod pool;
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use ethers::{
+    abi::{ParamType, Token},
+    providers::{Provider, Ws},
+    types::{Address, U256},
+};
+use serde::{Deserialize, Serialize};
+
+pub use pool::{PoolState, Swap};
+
+/// Signature of `QuoterV2.quoteExactInputSingle`
+const QUOTE_EXACT_INPUT_SINGLE: &str =
+    "quoteExactInputSingle((address,address,uint256,uint24,uint160))";
+
+/// Pool state with the quoter results recorded against it at the same block
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Fixture {
+    /// Where the quotes come from
+    pub source: String,
+    pub pool: PoolState,
+    pub quotes: Vec<RecordedQuote>,
+}
+
+/// Result of `QuoterV2.quoteExactInputSingle` without a price limit
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedQuote {
+    pub zero_for_one: bool,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub sqrt_price_x96_after: U256,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            std::fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("Could not parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Could not write {}", path.display()))
+    }
+}
+
+/// Asks `quoter`, a `QuoterV2`, what selling `amount_in` to `pool` returns at
+/// the block the pool state was read at
+pub async fn quote_exact_input_single(
+    provider: &Provider<Ws>,
+    quoter: Address,
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_in: U256,
+) -> Result<RecordedQuote> {
+    let (token_in, token_out) = if zero_for_one {
+        (pool.token0, pool.token1)
+    } else {
+        (pool.token1, pool.token0)
+    };
+    let params = Token::Tuple(vec![
+        Token::Address(token_in),
+        Token::Address(token_out),
+        Token::Uint(amount_in),
+        Token::Uint(pool.fee.into()),
+        Token::Uint(U256::zero()),
+    ]);
+    let output = pool::call(
+        provider,
+        quoter,
+        QUOTE_EXACT_INPUT_SINGLE,
+        vec![params],
+        vec![
+            ParamType::Uint(256),
+            ParamType::Uint(160),
+            ParamType::Uint(32),
+            ParamType::Uint(256),
+        ],
+        pool.block_number,
+    )
+    .await?;
+    Ok(RecordedQuote {
+        zero_for_one,
+        amount_in,
+        amount_out: pool::uint(&output[0])?,
+        sqrt_price_x96_after: pool::uint(&output[1])?,
+    })
+}
+
+/// Simulates `exactInputSingle` selling `amount_in` of `token_in` to `pool`
+pub fn exact_input_single(
+    pool: &PoolState,
+    token_in: Address,
+    amount_in: U256,
+    sqrt_price_limit_x96: Option<U256>,
+) -> Result<Swap> {
+    let zero_for_one = if token_in == pool.token0 {
+        true
+    } else if token_in == pool.token1 {
+        false
+    } else {
+        bail!("{:?} does not trade {:?}", pool.address, token_in);
+    };
+    pool.swap(zero_for_one, amount_in, sqrt_price_limit_x96)
+}
+
+/// Simulates `exactInput` selling `amount_in` of `token_in` along `pools`,
+/// returning the swap of each hop. Fails if a hop hits the extreme prices
+/// with input left, where the router would revert
+pub fn exact_input(pools: &[PoolState], token_in: Address, amount_in: U256) -> Result<Vec<Swap>> {
+    let mut swaps = Vec::with_capacity(pools.len());
+    let (mut token, mut amount) = (token_in, amount_in);
+    for pool in pools {
+        let swap = exact_input_single(pool, token, amount, None)?;
+        if swap.amount_in != amount {
+            bail!("{:?} ran out of liquidity", pool.address);
+        }
+        token = if token == pool.token0 {
+            pool.token1
+        } else {
+            pool.token0
+        };
+        amount = swap.amount_out;
+        swaps.push(swap);
+    }
+    Ok(swaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::providers::Middleware;
+
+    use super::*;
+
+    fn dir() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/v3")
+    }
+
+    /// Pool with a few overlapping positions around tick 0, all ticks known
+    fn synthetic() -> PoolState {
+        Fixture::load(&dir().join("synthetic.json")).unwrap().pool
+    }
+
+    /// Every fixture under `fixtures/v3`
+    fn fixtures() -> Vec<(String, Fixture)> {
+        let dir = dir();
+        let mut fixtures: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .map(|path| (path.display().to_string(), Fixture::load(&path).unwrap()))
+            .collect();
+        fixtures.sort_by(|a, b| a.0.cmp(&b.0));
+        fixtures
+    }
+
+    #[test]
+    fn matches_recorded_quotes() {
+        let fixtures = fixtures();
+        assert!(!fixtures.is_empty());
+        for (path, fixture) in fixtures {
+            for quote in &fixture.quotes {
+                let swap = fixture
+                    .pool
+                    .swap(quote.zero_for_one, quote.amount_in, None)
+                    .unwrap();
+                assert_eq!(swap.amount_out, quote.amount_out, "{} {:?}", path, quote);
+                assert_eq!(
+                    swap.sqrt_price_x96, quote.sqrt_price_x96_after,
+                    "{} {:?}",
+                    path, quote
+                );
+            }
+        }
+    }
+
+    /// Simulates swaps on the USDC/WETH 0.05% pool and asks the mainnet QuoterV2
+    /// for the same swaps at the same block. Needs `ETH_WS_URL`, run with
+    /// `cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn matches_the_mainnet_quoter() {
+        let pool: Address = "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640"
+            .parse()
+            .unwrap();
+        let quoter: Address = "0x61fFE014bA17989E743c5F6cB21bF9697530B21e"
+            .parse()
+            .unwrap();
+        let url = std::env::var("ETH_WS_URL").expect("ETH_WS_URL is not set");
+        let provider = Provider::<Ws>::connect(url).await.unwrap();
+        let block = provider.get_block_number().await.unwrap().as_u64();
+        let pool = PoolState::load(&provider, pool, 8, block).await.unwrap();
+
+        // 1,000 USDC, then 1 and 1,000 WETH
+        for (zero_for_one, amount_in) in [
+            (true, U256::exp10(9)),
+            (false, U256::exp10(18)),
+            (false, U256::exp10(21)),
+        ] {
+            let quote = quote_exact_input_single(&provider, quoter, &pool, zero_for_one, amount_in)
+                .await
+                .unwrap();
+            let swap = pool.swap(zero_for_one, amount_in, None).unwrap();
+            assert_eq!(swap.amount_out, quote.amount_out, "{:?}", quote);
+            assert_eq!(
+                swap.sqrt_price_x96, quote.sqrt_price_x96_after,
+                "{:?}",
+                quote
+            );
+        }
+    }
+
+    #[test]
+    fn chains_hops() {
+        let pool = synthetic();
+        let amount = U256::exp10(18);
+
+        let single = exact_input_single(&pool, pool.token0, amount, None).unwrap();
+        let swaps = exact_input(std::slice::from_ref(&pool), pool.token0, amount).unwrap();
+        assert_eq!(swaps, vec![single]);
+
+        // Out and back loses the fee twice
+        let swaps = exact_input(&[pool.clone(), pool.clone()], pool.token0, amount).unwrap();
+        assert_eq!(swaps[1].amount_in, swaps[0].amount_out);
+        assert!(swaps[1].amount_out < amount);
+
+        assert!(exact_input_single(&pool, Address::zero(), amount, None).is_err());
+    }
+
+    #[test]
+    fn needs_the_ticks_crossed() {
+        let pool = synthetic();
+        let word = (pool.tick.div_euclid(pool.tick_spacing) >> 8) as i16;
+        let pool = PoolState {
+            words: Some((word, word)),
+            ..pool
+        };
+        assert!(pool.swap(true, U256::exp10(18), None).is_ok());
+        assert!(pool.swap(true, U256::exp10(24), None).is_err());
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, ensure, Context, Result};
+use ethers::{
+    abi::{decode, encode, ParamType, Token},
+    providers::{Middleware, Provider, Ws},
+    types::{Address, Bytes, TransactionRequest, I256, U256},
+    utils::id,
+};
+use serde::{Deserialize, Serialize};
+
+use super::math::{
+    compute_swap_step, max_sqrt_ratio, min_sqrt_ratio, sqrt_ratio_at_tick, tick_at_sqrt_ratio,
+    MAX_TICK, MIN_TICK,
+};
+
+/// State of a V3 pool at a block, enough to simulate swaps against it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolState {
+    pub address: Address,
+    pub token0: Address,
+    pub token1: Address,
+    /// Fee tier in hundredths of a bip
+    pub fee: u32,
+    pub tick_spacing: i32,
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    /// Liquidity in range at the current tick
+    pub liquidity: u128,
+    /// Liquidity added when crossing each initialized tick upwards
+    pub ticks: BTreeMap<i32, i128>,
+    /// Tick bitmap words `ticks` was read from, inclusive. `None` when it holds
+    /// every initialized tick of the pool
+    pub words: Option<(i16, i16)>,
+    pub block_number: u64,
+}
+
+/// Result of an exact input swap against a pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Swap {
+    /// Input actually taken, less than requested if the price limit was reached
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+}
+
+impl PoolState {
+    /// Reads the state of `pool` at `block`, with the initialized ticks of the
+    /// `words` bitmap words on either side of the current tick
+    pub async fn load(
+        provider: &Provider<Ws>,
+        pool: Address,
+        words: i16,
+        block: u64,
+    ) -> Result<Self> {
+        let call = |signature, args, output| call(provider, pool, signature, args, output, block);
+
+        let slot0 = call(
+            "slot0()",
+            vec![],
+            vec![
+                ParamType::Uint(160),
+                ParamType::Int(24),
+                ParamType::Uint(16),
+                ParamType::Uint(16),
+                ParamType::Uint(16),
+                ParamType::Uint(8),
+                ParamType::Bool,
+            ],
+        )
+        .await?;
+        let token0 = call("token0()", vec![], vec![ParamType::Address]).await?;
+        let token1 = call("token1()", vec![], vec![ParamType::Address]).await?;
+        let fee = call("fee()", vec![], vec![ParamType::Uint(24)]).await?;
+        let tick_spacing = call("tickSpacing()", vec![], vec![ParamType::Int(24)]).await?;
+        let liquidity = call("liquidity()", vec![], vec![ParamType::Uint(128)]).await?;
+
+        let mut state = Self {
+            address: pool,
+            token0: address(&token0[0])?,
+            token1: address(&token1[0])?,
+            fee: narrow(uint(&fee[0])?)?,
+            tick_spacing: narrow(int(&tick_spacing[0])?)?,
+            sqrt_price_x96: uint(&slot0[0])?,
+            tick: narrow(int(&slot0[1])?)?,
+            liquidity: narrow(uint(&liquidity[0])?)?,
+            ticks: BTreeMap::new(),
+            words: None,
+            block_number: block,
+        };
+        ensure!(state.tick_spacing > 0, "{:?} is not a V3 pool", pool);
+
+        let word = (state.tick.div_euclid(state.tick_spacing) >> 8) as i16;
+        let (first, last) = (word.saturating_sub(words), word.saturating_add(words));
+        for word in first..=last {
+            let bitmap = call(
+                "tickBitmap(int16)",
+                vec![Token::Int(I256::from(word).into_raw())],
+                vec![ParamType::Uint(256)],
+            )
+            .await?;
+            let bitmap = uint(&bitmap[0])?;
+            for bit in (0..256).filter(|bit| bitmap.bit(*bit)) {
+                let tick = (word as i32 * 256 + bit as i32) * state.tick_spacing;
+                let info = call(
+                    "ticks(int24)",
+                    vec![Token::Int(I256::from(tick).into_raw())],
+                    vec![
+                        ParamType::Uint(128),
+                        ParamType::Int(128),
+                        ParamType::Uint(256),
+                        ParamType::Uint(256),
+                        ParamType::Int(56),
+                        ParamType::Uint(160),
+                        ParamType::Uint(32),
+                        ParamType::Bool,
+                    ],
+                )
+                .await?;
+                state.ticks.insert(tick, narrow(int(&info[1])?)?);
+            }
+        }
+        state.words = Some((first, last));
+        Ok(state)
+    }
+
+    /// Simulates swapping exactly `amount_in` into the pool, as
+    /// `UniswapV3Pool.swap` with a positive amount. Without a price limit the
+    /// swap may run to the extreme prices, as quoters allow
+    pub fn swap(
+        &self,
+        zero_for_one: bool,
+        amount_in: U256,
+        sqrt_price_limit_x96: Option<U256>,
+    ) -> Result<Swap> {
+        ensure!(!amount_in.is_zero(), "Nothing to swap");
+        let limit = sqrt_price_limit_x96.unwrap_or_else(|| {
+            if zero_for_one {
+                min_sqrt_ratio() + 1
+            } else {
+                max_sqrt_ratio() - 1
+            }
+        });
+        let valid_limit = if zero_for_one {
+            limit < self.sqrt_price_x96 && limit > min_sqrt_ratio()
+        } else {
+            limit > self.sqrt_price_x96 && limit < max_sqrt_ratio()
+        };
+        ensure!(valid_limit, "Price limit {} is out of bounds", limit);
+
+        let mut remaining = amount_in;
+        let mut amount_out = U256::zero();
+        let mut sqrt_price_x96 = self.sqrt_price_x96;
+        let mut tick = self.tick;
+        let mut liquidity = self.liquidity;
+
+        // One step per initialized tick or bitmap word, whichever comes first
+        while !remaining.is_zero() && sqrt_price_x96 != limit {
+            let start = sqrt_price_x96;
+            let (next, initialized) = self.next_initialized_tick(tick, zero_for_one)?;
+            let next = next.clamp(MIN_TICK, MAX_TICK);
+            let sqrt_price_next_x96 = sqrt_ratio_at_tick(next).context("Tick out of bounds")?;
+            let target = if (zero_for_one && sqrt_price_next_x96 < limit)
+                || (!zero_for_one && sqrt_price_next_x96 > limit)
+            {
+                limit
+            } else {
+                sqrt_price_next_x96
+            };
+
+            let step = compute_swap_step(start, target, liquidity, remaining, self.fee)
+                .context("Swap math overflowed")?;
+            sqrt_price_x96 = step.sqrt_price_next_x96;
+            remaining -= step.amount_in + step.fee_amount;
+            amount_out += step.amount_out;
+
+            if sqrt_price_x96 == sqrt_price_next_x96 {
+                if initialized {
+                    let net = self.ticks[&next];
+                    let net = if zero_for_one { -net } else { net };
+                    liquidity = liquidity
+                        .checked_add_signed(net)
+                        .context("Liquidity out of bounds")?;
+                }
+                tick = if zero_for_one { next - 1 } else { next };
+            } else if sqrt_price_x96 != start {
+                tick = tick_at_sqrt_ratio(sqrt_price_x96).context("Price out of bounds")?;
+            }
+        }
+
+        Ok(Swap {
+            amount_in: amount_in - remaining,
+            amount_out,
+            sqrt_price_x96,
+            tick,
+        })
+    }
+
+    /// Next initialized tick at or below `tick` (`lte`) or above it, within the
+    /// bitmap word of the start tick, as `TickBitmap.nextInitializedTickWithinOneWord`.
+    /// Returns the word boundary when no tick is initialized in between
+    fn next_initialized_tick(&self, tick: i32, lte: bool) -> Result<(i32, bool)> {
+        let compressed = tick.div_euclid(self.tick_spacing);
+        let (from, to) = if lte {
+            (compressed - compressed.rem_euclid(256), compressed)
+        } else {
+            let compressed = compressed + 1;
+            let start = compressed - compressed.rem_euclid(256);
+            (compressed, start + 255)
+        };
+
+        let word = from.div_euclid(256);
+        if let Some((first, last)) = self.words {
+            if word < first as i32 || word > last as i32 {
+                bail!(
+                    "State of {:?} does not cover tick bitmap word {}",
+                    self.address,
+                    word
+                );
+            }
+        }
+
+        let mut ticks = self
+            .ticks
+            .range(from * self.tick_spacing..=to * self.tick_spacing)
+            .map(|(tick, _)| *tick);
+        let found = if lte { ticks.next_back() } else { ticks.next() };
+        Ok(match found {
+            Some(tick) => (tick, true),
+            None if lte => (from * self.tick_spacing, false),
+            None => (to * self.tick_spacing, false),
+        })
+    }
+}
+
+/// Calls `signature` on `to` at `block`, decoding the `output` types
+pub(super) async fn call(
+    provider: &Provider<Ws>,
+    to: Address,
+    signature: &str,
+    args: Vec<Token>,
+    output: Vec<ParamType>,
+    block: u64,
+) -> Result<Vec<Token>> {
+    let data = [&id(signature)[..], &encode(&args)].concat();
+    let request = TransactionRequest::new().to(to).data(Bytes::from(data));
+    let result = provider
+        .call(&request.into(), Some(block.into()))
+        .await
+        .with_context(|| format!("Could not call {} on {:?}", signature, to))?;
+    decode(&output, &result).with_context(|| format!("{:?} returned a malformed {}", to, signature))
+}
+
+pub(super) fn uint(token: &Token) -> Result<U256> {
+    token
+        .clone()
+        .into_uint()
+        .context("Expected an unsigned integer")
+}
+
+fn int(token: &Token) -> Result<I256> {
+    token
+        .clone()
+        .into_int()
+        .map(I256::from_raw)
+        .context("Expected a signed integer")
+}
+
+/// Converts a decoded integer to a narrower type, failing when it does not fit
+fn narrow<T, N>(value: T) -> Result<N>
+where
+    T: Copy + std::fmt::Display,
+    N: TryFrom<T>,
+{
+    N::try_from(value)
+        .ok()
+        .with_context(|| format!("{} is out of range", value))
+}
+
+fn address(token: &Token) -> Result<Address> {
+    token.clone().into_address().context("Expected an address")
+}
@@ -0,0 +1,464 @@
+use std::collections::{HashMap, HashSet};
+
+use ethers::{
+    addressbook::Chain,
+    types::{Address, I256, U256, U512},
+};
+
+use super::peak;
+use crate::{
+    amm::v2::{get_amounts_out, hop_reserves},
+    config::ArbitrageConfig,
+    model::{ArbitrageOpportunity, Intent, Reserves, RouterEvent, SwapIntent},
+    pairs::PairRegistry,
+};
+
+/// Round trip through V2 pairs, starting and ending with the same token
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Cycle {
+    path: Vec<Address>,
+    pools: Vec<Address>,
+}
+
+/// Finds round trips through the registered V2 pairs that the pending swaps of
+/// router events make profitable
+///
+/// Round trips start and end with the wrapped native token, go through a pair
+/// the swap trades against and take two or three hops. Pairs of the swap are
+/// valued as if it were mined, the others at the latest reserves seen
+#[derive(Debug)]
+pub struct ArbitrageDetector {
+    chain: Chain,
+    config: ArbitrageConfig,
+    wrapped_native: Address,
+}
+
+impl ArbitrageDetector {
+    pub fn new(chain: Chain, config: ArbitrageConfig, wrapped_native: Address) -> Self {
+        Self {
+            chain,
+            config,
+            wrapped_native,
+        }
+    }
+
+    /// Opportunities behind the swaps of `event`, which must carry quotes.
+    /// `reserves` returns the latest reserves seen of a pair
+    pub fn detect(
+        &self,
+        event: &RouterEvent,
+        pairs: &PairRegistry,
+        reserves: impl Fn(Address) -> Option<Reserves>,
+    ) -> Vec<ArbitrageOpportunity> {
+        let mut opportunities = vec![];
+        for intent in &event.intents {
+            let Intent::Swap(swap) = intent else {
+                continue;
+            };
+            if swap.version != 2 {
+                continue;
+            }
+            let Some(moved) = post_swap_reserves(swap) else {
+                continue;
+            };
+
+            let mut seen = HashSet::new();
+            for (hop, pool) in swap.path.windows(2).zip(&swap.pools) {
+                let limit = self.config.max_cycles.saturating_sub(seen.len());
+                for cycle in self.cycles(pairs, *pool, hop[0], hop[1], limit) {
+                    if !seen.insert(cycle.pools.clone()) {
+                        continue;
+                    }
+                    // Pairs not traded by the swap are taken as last seen
+                    let Some(cycle_reserves) = cycle
+                        .pools
+                        .iter()
+                        .map(|pool| moved.get(pool).copied().or_else(|| reserves(*pool)))
+                        .collect::<Option<Vec<_>>>()
+                    else {
+                        continue;
+                    };
+                    let block_number = cycle_reserves
+                        .iter()
+                        .map(|reserves| reserves.block_number)
+                        .min()
+                        .unwrap_or_default();
+                    let Some(hops) = hop_reserves(&cycle.path, &cycle_reserves) else {
+                        continue;
+                    };
+                    let Some((amount_in, amount_out)) = optimal(&hops) else {
+                        continue;
+                    };
+                    let profit = amount_out - amount_in;
+                    if profit < self.config.min_profit.into() {
+                        continue;
+                    }
+                    opportunities.push(ArbitrageOpportunity {
+                        chain_id: self.chain.into(),
+                        hash: event.transaction.hash,
+                        router: event.router.name.clone(),
+                        trigger_pool: *pool,
+                        path: cycle.path,
+                        pools: cycle.pools,
+                        amount_in,
+                        amount_out,
+                        profit,
+                        block_number,
+                    });
+                }
+            }
+        }
+        opportunities
+    }
+
+    /// Up to `limit` round trips from the wrapped native token through `pool`,
+    /// which trades `a` for `b`, in both directions
+    fn cycles(
+        &self,
+        pairs: &PairRegistry,
+        pool: Address,
+        a: Address,
+        b: Address,
+        limit: usize,
+    ) -> Vec<Cycle> {
+        let native = self.wrapped_native;
+        let mut cycles = vec![];
+        if a == native || b == native {
+            let token = if a == native { b } else { a };
+            // Back through another pair of the same tokens
+            for other in pairs.pairs_between(native, token) {
+                if other != pool {
+                    both_ways(&mut cycles, vec![native, token, native], vec![pool, other]);
+                }
+            }
+            // Or through a third token
+            for (third, hop) in pairs.pairs_of(token) {
+                if cycles.len() >= limit {
+                    break;
+                }
+                if third == native {
+                    continue;
+                }
+                for back in pairs.pairs_between(third, native) {
+                    both_ways(
+                        &mut cycles,
+                        vec![native, token, third, native],
+                        vec![pool, hop, back],
+                    );
+                }
+            }
+        } else {
+            for into in pairs.pairs_between(native, a) {
+                for back in pairs.pairs_between(b, native) {
+                    both_ways(
+                        &mut cycles,
+                        vec![native, a, b, native],
+                        vec![into, pool, back],
+                    );
+                }
+            }
+        }
+        cycles.truncate(limit);
+        cycles
+    }
+}
+
+/// Adds the round trip along `path` through `pools` and its reverse
+fn both_ways(cycles: &mut Vec<Cycle>, path: Vec<Address>, pools: Vec<Address>) {
+    cycles.push(Cycle {
+        path: path.iter().rev().copied().collect(),
+        pools: pools.iter().rev().copied().collect(),
+    });
+    cycles.push(Cycle { path, pools });
+}
+
+/// Reserves of the pairs of a quoted V2 swap once it is mined
+pub fn post_swap_reserves(swap: &SwapIntent) -> Option<HashMap<Address, Reserves>> {
+    let quote = swap.quote.as_ref()?;
+    if swap.pools.len() != swap.reserves.len() || quote.amounts.len() != swap.path.len() {
+        return None;
+    }
+    let mut moved = HashMap::new();
+    for (i, (pool, reserves)) in swap.pools.iter().zip(&swap.reserves).enumerate() {
+        // A pair traded twice along the path starts from its first trade
+        let mut reserves = *moved.get(pool).unwrap_or(reserves);
+        let (amount_in, amount_out) = (quote.amounts[i], quote.amounts[i + 1]);
+        if swap.path[i] < swap.path[i + 1] {
+            reserves.reserve0 = reserves.reserve0.checked_add(amount_in)?;
+            reserves.reserve1 = reserves.reserve1.checked_sub(amount_out)?;
+        } else {
+            reserves.reserve1 = reserves.reserve1.checked_add(amount_in)?;
+            reserves.reserve0 = reserves.reserve0.checked_sub(amount_out)?;
+        }
+        moved.insert(*pool, reserves);
+    }
+    Some(moved)
+}
+
+/// Most profitable input and its output through `hops`, the input and output
+/// reserves of each pair of a round trip. `None` unless the round trip gains
+///
+/// Inputs beyond the first pair's own reserve are not considered
+pub fn optimal(hops: &[(U256, U256)]) -> Option<(U256, U256)> {
+    // Tiny trades gain only if the product of the marginal prices, fees
+    // included, is above one
+    let (mut gained, mut paid) = (U512::one(), U512::one());
+    for &(reserve_in, reserve_out) in hops {
+        gained = gained.checked_mul(U512::from(reserve_out) * 997)?;
+        paid = paid.checked_mul(U512::from(reserve_in) * 1000)?;
+    }
+    if gained <= paid {
+        return None;
+    }
+
+    let simulate = |amount_in: U256| {
+        get_amounts_out(amount_in, hops).and_then(|amounts| amounts.last().copied())
+    };
+    let profit = |amount_in: U256| {
+        simulate(amount_in)
+            .map(|amount_out| I256::from_raw(amount_out) - I256::from_raw(amount_in))
+            .unwrap_or_else(I256::min_value)
+    };
+    let amount_in = peak(U256::one(), hops.first()?.0.max(U256::one()), profit);
+    let amount_out = simulate(amount_in)?;
+    (amount_out > amount_in).then_some((amount_in, amount_out))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ethers::{abi::Abi, types::Transaction};
+
+    use super::*;
+    use crate::{
+        amm::v2::get_amount_out,
+        config::PairsConfig,
+        contracts::{Factory, Router},
+        decoder::DecodedCall,
+        model::{SwapDirection, SwapKind},
+    };
+
+    /// Tokens sorted by address, the wrapped native token first
+    const W: u64 = 1;
+    const X: u64 = 2;
+    const Y: u64 = 3;
+    /// Factory of the registry in `fixtures/pairs`
+    const FACTORY: u64 = 0xfa;
+    /// Pair the victim trades against and the other pairs of the registry
+    const VICTIM: u64 = 0x12;
+    const OTHER: u64 = 0x120;
+    const XY: u64 = 0x23;
+    const WY: u64 = 0x13;
+
+    fn address(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn ether(amount: u64) -> U256 {
+        U256::exp10(18) * amount
+    }
+
+    fn reserves(reserve0: U256, reserve1: U256) -> Reserves {
+        Reserves {
+            reserve0,
+            reserve1,
+            block_number: 1,
+        }
+    }
+
+    /// Pairs of the registry: two W/X pairs, X/Y and W/Y
+    fn registry() -> PairRegistry {
+        let factory = Factory {
+            address: address(FACTORY),
+            abi: Abi::default(),
+            name: "factory".to_string(),
+            version: 2,
+            from_block: 0,
+            init_code_hash: None,
+        };
+        let config = PairsConfig {
+            dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/pairs"),
+            ..Default::default()
+        };
+        PairRegistry::load(Chain::Mainnet, &[factory], &config).unwrap()
+    }
+
+    /// The W/X pairs priced alike, and a round trip through Y priced alike too
+    fn market() -> HashMap<Address, Reserves> {
+        HashMap::from([
+            (address(VICTIM), reserves(ether(100), ether(200_000))),
+            (address(OTHER), reserves(ether(100), ether(200_000))),
+            (address(XY), reserves(ether(200_000), ether(200_000))),
+            (address(WY), reserves(ether(100), ether(200_000))),
+        ])
+    }
+
+    /// Pending swap of 10 W for X on the victim pair, quoted against `market`
+    fn victim(market: &HashMap<Address, Reserves>) -> RouterEvent {
+        let mut swap = SwapIntent {
+            function: "swapExactTokensForTokens".to_string(),
+            kind: SwapKind::ExactIn,
+            direction: SwapDirection::TokenToToken,
+            native_eth: false,
+            path: vec![address(W), address(X)],
+            amount_in: Some(ether(10)),
+            amount_out: U256::zero(),
+            recipient: None,
+            deadline: None,
+            fee_on_transfer: false,
+            version: 2,
+            fees: vec![],
+            pools: vec![address(VICTIM)],
+            reserves: vec![market[&address(VICTIM)]],
+            quote: None,
+        };
+        swap.quote = crate::amm::v2::quote(&swap);
+        let router = Router {
+            address: address(0xff),
+            abi: Abi::default(),
+            name: "router".to_string(),
+            version: 2,
+            factory: vec![],
+            wrapped_native: None,
+        };
+        let call = DecodedCall {
+            function: swap.function.clone(),
+            intents: vec![Intent::Swap(swap)],
+        };
+        RouterEvent::new(Chain::Mainnet, &Transaction::default(), &router, call)
+    }
+
+    /// Reserves of the victim pair once the victim's swap is mined
+    fn victim_after() -> Reserves {
+        let bought = get_amount_out(ether(10), ether(100), ether(200_000)).unwrap();
+        reserves(ether(110), ether(200_000) - bought)
+    }
+
+    fn float(value: U256) -> f64 {
+        value.as_u128() as f64
+    }
+
+    /// Optimal input and profit of a round trip through `hops`, from the
+    /// reserves of the single pool with the same output for every input
+    fn closed_form(hops: &[(U256, U256)]) -> (f64, f64) {
+        let fee = 0.997;
+        let (mut reserve_in, mut reserve_out) = (float(hops[0].0), float(hops[0].1));
+        for &(next_in, next_out) in &hops[1..] {
+            let (next_in, next_out) = (float(next_in), float(next_out));
+            let denominator = next_in + fee * reserve_out;
+            (reserve_in, reserve_out) = (
+                reserve_in * next_in / denominator,
+                fee * reserve_out * next_out / denominator,
+            );
+        }
+        let amount_in = ((fee * reserve_in * reserve_out).sqrt() - reserve_in) / fee;
+        let amount_out = fee * amount_in * reserve_out / (reserve_in + fee * amount_in);
+        (amount_in, amount_out - amount_in)
+    }
+
+    fn assert_optimal(opportunity: &ArbitrageOpportunity, hops: &[(U256, U256)]) {
+        let (amount_in, profit) = closed_form(hops);
+        let found_in = float(opportunity.amount_in);
+        let found_profit = float(opportunity.profit);
+        assert!(
+            (found_in - amount_in).abs() / amount_in < 1e-8,
+            "{} {}",
+            found_in,
+            amount_in
+        );
+        assert!(
+            (found_profit - profit).abs() / profit < 1e-12,
+            "{} {}",
+            found_profit,
+            profit
+        );
+        assert_eq!(
+            opportunity.amount_out,
+            opportunity.amount_in + opportunity.profit
+        );
+    }
+
+    fn detect(max_cycles: usize) -> Vec<ArbitrageOpportunity> {
+        let market = market();
+        let config = ArbitrageConfig {
+            max_cycles,
+            ..Default::default()
+        };
+        ArbitrageDetector::new(Chain::Mainnet, config, address(W)).detect(
+            &victim(&market),
+            &registry(),
+            |pool| market.get(&pool).copied(),
+        )
+    }
+
+    fn pools(opportunity: &ArbitrageOpportunity) -> Vec<u64> {
+        opportunity
+            .pools
+            .iter()
+            .map(|pool| pool.to_low_u64_be())
+            .collect()
+    }
+
+    #[test]
+    fn sells_back_through_another_pair() {
+        let opportunities = detect(256);
+        let opportunity = opportunities
+            .iter()
+            .find(|opportunity| pools(opportunity) == [OTHER, VICTIM])
+            .unwrap();
+        assert_eq!(opportunity.path, vec![address(W), address(X), address(W)]);
+        assert_eq!(opportunity.trigger_pool, address(VICTIM));
+
+        let after = victim_after();
+        assert_optimal(
+            opportunity,
+            &[
+                (ether(100), ether(200_000)),
+                (after.reserve1, after.reserve0),
+            ],
+        );
+    }
+
+    #[test]
+    fn sells_back_through_a_third_token() {
+        let opportunities = detect(256);
+        let opportunity = opportunities
+            .iter()
+            .find(|opportunity| pools(opportunity) == [WY, XY, VICTIM])
+            .unwrap();
+        assert_eq!(
+            opportunity.path,
+            vec![address(W), address(Y), address(X), address(W)]
+        );
+
+        let after = victim_after();
+        assert_optimal(
+            opportunity,
+            &[
+                (ether(100), ether(200_000)),
+                (ether(200_000), ether(200_000)),
+                (after.reserve1, after.reserve0),
+            ],
+        );
+    }
+
+    #[test]
+    fn only_gains_are_reported() {
+        let opportunities = detect(256);
+        assert_eq!(opportunities.len(), 2);
+        assert!(opportunities
+            .iter()
+            .all(|opportunity| opportunity.pools.last() == Some(&address(VICTIM))));
+    }
+
+    #[test]
+    fn examines_at_most_max_cycles() {
+        // Round trips are examined both ways, the one back through the other
+        // W/X pair first and the one through Y third
+        let first: Vec<_> = detect(1).iter().map(pools).collect();
+        assert_eq!(first, vec![vec![OTHER, VICTIM]]);
+        assert_eq!(detect(2).len(), 1);
+        assert_eq!(detect(3).len(), 2);
+    }
+}
@@ -0,0 +1,27 @@
+pub mod arbitrage;
+pub mod sandwich;
+
+use ethers::types::{I256, U256};
+
+/// Input within `low..=high` at which `profit` peaks, for profits that rise
+/// then fall with the input as they do across constant product pools. Ties go
+/// to the larger input, so that a bound on the input is used up when rounding
+/// leaves the profit flat near it
+fn peak(mut low: U256, mut high: U256, profit: impl Fn(U256) -> I256) -> U256 {
+    while high - low > U256::from(2) {
+        let third = (high - low) / 3;
+        if profit(low + third) <= profit(high - third) {
+            low = low + third + 1;
+        } else {
+            high -= third;
+        }
+    }
+    let mut best = low;
+    while low < high {
+        low += U256::one();
+        if profit(low) >= profit(best) {
+            best = low;
+        }
+    }
+    best
+}
@@ -0,0 +1,283 @@
+use std::sync::RwLock;
+
+use ethers::{
+    addressbook::Chain,
+    types::{Address, I256, U256},
+};
+
+use super::peak;
+use crate::{
+    amm::v2::{get_amount_out, get_amounts_out, hop_reserves},
+    config::SandwichConfig,
+    model::{Intent, RouterEvent, SandwichOpportunity, SwapIntent, SwapKind},
+};
+
+/// Legs of a sandwich around a victim swap, amounts in the victim's input token
+/// for the front-run input and back-run output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sandwich {
+    pub front_run_in: U256,
+    pub front_run_out: U256,
+    pub victim_amount_out: U256,
+    pub back_run_out: U256,
+}
+
+impl Sandwich {
+    fn profit(&self) -> I256 {
+        I256::from_raw(self.back_run_out) - I256::from_raw(self.front_run_in)
+    }
+}
+
+/// Finds the pending V2 swaps of router events that can be sandwiched at a profit
+///
+/// Only exact-input swaps selling the wrapped native token are considered, so
+/// that profits and gas are in the same unit. Swaps through the
+/// `SupportingFeeOnTransferTokens` router functions are skipped, their tokens
+/// likely take a fee that makes the legs do worse than computed
+#[derive(Debug)]
+pub struct SandwichDetector {
+    chain: Chain,
+    config: SandwichConfig,
+    wrapped_native: Address,
+    /// Base fee of the next block, unknown until a block is mined
+    base_fee: RwLock<Option<U256>>,
+}
+
+impl SandwichDetector {
+    pub fn new(chain: Chain, config: SandwichConfig, wrapped_native: Address) -> Self {
+        Self {
+            chain,
+            config,
+            wrapped_native,
+            base_fee: RwLock::default(),
+        }
+    }
+
+    /// Records the base fee of the block about to be built
+    pub fn set_base_fee(&self, base_fee: U256) {
+        *self.base_fee.write().unwrap_or_else(|e| e.into_inner()) = Some(base_fee);
+    }
+
+    /// Opportunities among the swaps of `event`, which must carry reserves
+    pub fn detect(&self, event: &RouterEvent) -> Vec<SandwichOpportunity> {
+        let Some(base_fee) = *self.base_fee.read().unwrap_or_else(|e| e.into_inner()) else {
+            return vec![];
+        };
+        let gas_price = base_fee + self.config.priority_fee;
+        let gas_cost = gas_price * self.config.gas_per_swap * 2;
+
+        event
+            .intents
+            .iter()
+            .filter_map(|intent| match intent {
+                Intent::Swap(swap) => Some(swap),
+                Intent::Liquidity(_) => None,
+            })
+            .filter(|swap| self.targets(swap))
+            .filter_map(|swap| {
+                let sandwich = optimal(swap)?;
+                let gross_profit = sandwich.back_run_out - sandwich.front_run_in;
+                let net_profit = gross_profit.checked_sub(gas_cost)?;
+                if net_profit.is_zero() || net_profit < self.config.min_profit.into() {
+                    return None;
+                }
+                Some(SandwichOpportunity {
+                    chain_id: self.chain.into(),
+                    hash: event.transaction.hash,
+                    router: event.router.name.clone(),
+                    pair: swap.pools[0],
+                    token_in: swap.path[0],
+                    token_out: swap.path[1],
+                    front_run_in: sandwich.front_run_in,
+                    front_run_out: sandwich.front_run_out,
+                    victim_amount_out: sandwich.victim_amount_out,
+                    victim_amount_out_min: swap.amount_out,
+                    back_run_out: sandwich.back_run_out,
+                    gross_profit,
+                    gas_cost,
+                    net_profit,
+                    base_fee,
+                    block_number: swap.reserves[0].block_number,
+                })
+            })
+            .collect()
+    }
+
+    /// Whether `swap` is one the detector looks at
+    fn targets(&self, swap: &SwapIntent) -> bool {
+        swap.version == 2
+            && swap.kind == SwapKind::ExactIn
+            && swap.path.first() == Some(&self.wrapped_native)
+            && !swap.fee_on_transfer
+    }
+}
+
+/// Most profitable sandwich of an exact-input V2 swap against its attached
+/// reserves, before gas. `None` if no front-run leaves the victim its minimum
+/// output at a profit
+///
+/// Both legs trade against the first pair of the path; the victim's later hops
+/// only matter through its minimum output
+pub fn optimal(swap: &SwapIntent) -> Option<Sandwich> {
+    let amount_in = swap.amount_in?;
+    let hops = hop_reserves(&swap.path, &swap.reserves)?;
+    let (reserve_in, reserve_out) = *hops.first()?;
+    let simulate = |front_run_in: U256| -> Option<Sandwich> {
+        let front_run_out = get_amount_out(front_run_in, reserve_in, reserve_out)?;
+        let mut hops = hops.clone();
+        hops[0] = (
+            reserve_in.checked_add(front_run_in)?,
+            reserve_out.checked_sub(front_run_out)?,
+        );
+        let amounts = get_amounts_out(amount_in, &hops)?;
+        // A front-run too small to buy anything has nothing to sell back
+        let back_run_out = if front_run_out.is_zero() {
+            U256::zero()
+        } else {
+            get_amount_out(
+                front_run_out,
+                hops[0].1.checked_sub(amounts[1])?,
+                hops[0].0.checked_add(amount_in)?,
+            )?
+        };
+        Some(Sandwich {
+            front_run_in,
+            front_run_out,
+            victim_amount_out: amounts[amounts.len() - 1],
+            back_run_out,
+        })
+    };
+    let victim_ok = |sandwich: &Sandwich| sandwich.victim_amount_out >= swap.amount_out;
+    // Nothing to do if the victim has no room left at all
+    simulate(U256::one()).filter(victim_ok)?;
+
+    // The victim's output falls as the front-run grows, so the largest front-run
+    // it tolerates is found by bisection. Front-runs beyond the pair's own
+    // reserve are not considered
+    let (mut low, mut high) = (U256::zero(), reserve_in);
+    if simulate(high).filter(victim_ok).is_some() {
+        low = high;
+    }
+    while high - low > U256::one() {
+        let middle = low + (high - low) / 2;
+        if simulate(middle).filter(victim_ok).is_some() {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    let largest = low.max(U256::one());
+
+    let best = peak(U256::one(), largest, |front_run_in| {
+        simulate(front_run_in)
+            .map(|sandwich| sandwich.profit())
+            .unwrap_or_else(I256::min_value)
+    });
+    simulate(best).filter(|sandwich| sandwich.profit() > I256::zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Reserves, SwapDirection};
+
+    fn token(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn ether(amount: u64) -> U256 {
+        U256::exp10(18) * amount
+    }
+
+    /// 100 of the wrapped native token against 200,000 of another token
+    fn reserves() -> Reserves {
+        Reserves {
+            reserve0: ether(100),
+            reserve1: ether(200_000),
+            block_number: 1,
+        }
+    }
+
+    /// Victim selling 5 of the wrapped native token for at least `amount_out_min`
+    fn victim(amount_out_min: U256) -> SwapIntent {
+        SwapIntent {
+            function: "swapExactTokensForTokens".to_string(),
+            kind: SwapKind::ExactIn,
+            direction: SwapDirection::TokenToToken,
+            native_eth: false,
+            path: vec![token(1), token(2)],
+            amount_in: Some(ether(5)),
+            amount_out: amount_out_min,
+            recipient: None,
+            deadline: None,
+            fee_on_transfer: false,
+            version: 2,
+            fees: vec![],
+            pools: vec![token(0x12)],
+            reserves: vec![reserves()],
+            quote: None,
+        }
+    }
+
+    /// What the victim gets behind a front-run of `front_run_in`
+    fn victim_out(front_run_in: U256) -> U256 {
+        let reserves = reserves();
+        let front_run_out =
+            get_amount_out(front_run_in, reserves.reserve0, reserves.reserve1).unwrap();
+        get_amount_out(
+            ether(5),
+            reserves.reserve0 + front_run_in,
+            reserves.reserve1 - front_run_out,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn leaves_the_victim_its_minimum() {
+        // Slack for a front-run of 2 of the wrapped native token
+        let minimum = victim_out(ether(2));
+        let sandwich = optimal(&victim(minimum)).unwrap();
+        assert_eq!(sandwich.victim_amount_out, minimum);
+        assert!(sandwich.front_run_in >= ether(2));
+        assert!(victim_out(sandwich.front_run_in + 1) < minimum);
+        assert_eq!(
+            sandwich.victim_amount_out,
+            victim_out(sandwich.front_run_in)
+        );
+        assert!(sandwich.back_run_out > sandwich.front_run_in);
+    }
+
+    #[test]
+    fn needs_slack() {
+        let reserves = reserves();
+        let quoted = get_amount_out(ether(5), reserves.reserve0, reserves.reserve1).unwrap();
+        assert_eq!(optimal(&victim(quoted)), None);
+        assert_eq!(optimal(&victim(quoted + 1)), None);
+    }
+
+    #[test]
+    fn targets_plain_exact_input_sales_of_the_wrapped_native_token() {
+        let detector = SandwichDetector::new(Chain::Mainnet, SandwichConfig::default(), token(1));
+        let swap = victim(U256::zero());
+        assert!(detector.targets(&swap));
+
+        let fee_on_transfer = SwapIntent {
+            function: "swapExactTokensForTokensSupportingFeeOnTransferTokens".to_string(),
+            fee_on_transfer: true,
+            ..swap.clone()
+        };
+        assert!(!detector.targets(&fee_on_transfer));
+
+        let buying = SwapIntent {
+            path: vec![token(2), token(1)],
+            ..swap.clone()
+        };
+        assert!(!detector.targets(&buying));
+
+        let exact_out = SwapIntent {
+            kind: SwapKind::ExactOut,
+            ..swap
+        };
+        assert!(!detector.targets(&exact_out));
+    }
+}
@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use ethers::core::rand::{thread_rng, Rng};
+
+/// Exponential backoff with jitter, doubling from `initial` up to `max`
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            attempt: 0,
+        }
+    }
+
+    /// Number of delays handed out since the last reset
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Returns the delay before the next attempt
+    ///
+    /// Half the delay is fixed and half is random, so watchers that lost the
+    /// same node do not all reconnect at the same instant
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = delay / 2;
+        half + half.mul_f64(thread_rng().gen::<f64>())
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
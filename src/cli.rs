@@ -0,0 +1,243 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use ethers::{
+    providers::{Middleware, Provider, Ws},
+    types::{Address, U256},
+};
+
+use crate::{
+    abi::CacheSource,
+    amm::v3::{self, Fixture, PoolState},
+    config::{AbiSourceKind, Config},
+    format::Format,
+};
+
+/// Watches pending transactions for calls to Uniswap routers
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Watcher configuration file
+    #[arg(long, env = "WATCHER_CONFIG", default_value = "watcher.toml")]
+    pub config: PathBuf,
+
+    /// Format of events printed on stdout, overriding the configured one
+    #[arg(long, value_enum)]
+    pub format: Option<Format>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Watch the configured chains (the default)
+    Watch,
+    /// Inspect and maintain the ABI cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Simulate a V3 exact input swap against pool state read over RPC or from fixtures
+    Quote(QuoteArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// List cached ABIs with their source and age
+    List,
+    /// Remove expired entries
+    Prune {
+        /// Remove every entry, expired or not
+        #[arg(long)]
+        all: bool,
+    },
+    /// Resolve and cache the ABI of every configured factory and router
+    Prewarm,
+}
+
+#[derive(Debug, Args)]
+pub struct QuoteArgs {
+    /// Chain the pools are read from, as named in the configuration
+    #[arg(long)]
+    pub chain: Option<String>,
+    /// Pool of each hop, in order
+    #[arg(long = "pool", required_unless_present = "fixtures")]
+    pub pools: Vec<Address>,
+    /// Fixture holding the state of each hop's pool, in order, instead of `--pool`
+    #[arg(long = "fixture", conflicts_with_all = ["pools", "chain"])]
+    pub fixtures: Vec<PathBuf>,
+    /// Token sold
+    #[arg(long)]
+    pub token_in: Address,
+    /// Amount sold, in the token's smallest unit
+    #[arg(long)]
+    pub amount_in: String,
+    /// Block the pools and the quoter are read at, the latest one by default
+    #[arg(long, conflicts_with = "fixtures")]
+    pub block: Option<u64>,
+    /// Tick bitmap words read on either side of each pool's current tick
+    #[arg(long, default_value_t = 4)]
+    pub words: i16,
+    /// QuoterV2 to check a single hop against
+    #[arg(long)]
+    pub quoter: Option<Address>,
+    /// Save the pool state and the quoter's result as a test fixture, adding
+    /// the result to the fixture already recorded there for the same block
+    #[arg(long, requires = "quoter")]
+    pub record: Option<PathBuf>,
+}
+
+/// Runs a `cache` subcommand
+pub async fn cache(config: &Config, command: CacheCommand) -> Result<()> {
+    let cache = CacheSource::from_config(&config.abi);
+
+    match command {
+        CacheCommand::List => {
+            for entry in cache.entries()? {
+                let expired = if cache.is_expired(&entry) {
+                    " (expired)"
+                } else {
+                    ""
+                };
+                let implementation = entry
+                    .implementation
+                    .map(|implementation| format!(" -> {:?}", implementation))
+                    .unwrap_or_default();
+                println!(
+                    "{:>6} {:?} {:<9} {:>5}h{}{}",
+                    entry.chain_id,
+                    entry.address,
+                    entry.source,
+                    entry.age().as_secs() / 3600,
+                    expired,
+                    implementation
+                );
+            }
+        }
+        CacheCommand::Prune { all } => {
+            println!("Removed {} cache entries", cache.prune(all)?);
+        }
+        CacheCommand::Prewarm => {
+            // Without the cache source, resolved ABIs would not be saved anywhere
+            ensure!(
+                config.abi.sources.contains(&AbiSourceKind::Cache),
+                "Nothing to prewarm, add \"cache\" to abi.sources"
+            );
+            for chain in &config.chains {
+                let provider = Provider::<Ws>::connect(chain.ws_url()?).await?;
+                let routers = chain.routers(&config.abi, provider).await?;
+                println!("{}: cached ABIs of {} routers", chain.name, routers.len());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the `quote` subcommand
+pub async fn quote(config: &Config, args: QuoteArgs) -> Result<()> {
+    let amount_in = U256::from_dec_str(&args.amount_in)
+        .with_context(|| format!("Invalid amount {}", args.amount_in))?;
+
+    let (pools, provider) = if args.fixtures.is_empty() {
+        let name = args
+            .chain
+            .as_deref()
+            .context("--chain is needed to read pools over RPC")?;
+        let chain = config
+            .chains
+            .iter()
+            .find(|chain| chain.name == name)
+            .with_context(|| format!("Chain {} is not configured", name))?;
+        let provider = Provider::<Ws>::connect(chain.ws_url()?).await?;
+        // Every pool and the quoter are read at the same block
+        let block = match args.block {
+            Some(block) => block,
+            None => provider.get_block_number().await?.as_u64(),
+        };
+        let mut pools = vec![];
+        for pool in &args.pools {
+            pools.push(PoolState::load(&provider, *pool, args.words, block).await?);
+        }
+        (pools, Some(provider))
+    } else {
+        let pools = args
+            .fixtures
+            .iter()
+            .map(|path| Fixture::load(path).map(|fixture| fixture.pool))
+            .collect::<Result<_>>()?;
+        (pools, None)
+    };
+
+    let swaps = v3::exact_input(&pools, args.token_in, amount_in)?;
+    for (pool, swap) in pools.iter().zip(&swaps) {
+        println!(
+            "{:?} ({} pips) at block {}: {} -> {}, sqrt price {} at tick {}",
+            pool.address,
+            pool.fee,
+            pool.block_number,
+            swap.amount_in,
+            swap.amount_out,
+            swap.sqrt_price_x96,
+            swap.tick
+        );
+    }
+
+    let Some(quoter) = args.quoter else {
+        return Ok(());
+    };
+    let (Some(provider), [pool]) = (provider, pools.as_slice()) else {
+        bail!("--quoter needs a single pool read over RPC");
+    };
+    let zero_for_one = args.token_in == pool.token0;
+    let quote =
+        v3::quote_exact_input_single(&provider, quoter, pool, zero_for_one, amount_in).await?;
+    println!(
+        "QuoterV2 {:?}: {} -> {}, sqrt price {}",
+        quoter, quote.amount_in, quote.amount_out, quote.sqrt_price_x96_after
+    );
+    if let Some(path) = args.record {
+        let mut fixture = if path.exists() {
+            let fixture = Fixture::load(&path)?;
+            ensure!(
+                fixture.pool.address == pool.address
+                    && fixture.pool.block_number == pool.block_number,
+                "{} holds {:?} at block {}, pass --block to add to it",
+                path.display(),
+                fixture.pool.address,
+                fixture.pool.block_number
+            );
+            fixture
+        } else {
+            Fixture {
+                source: format!(
+                    "QuoterV2 {:?} on {}",
+                    quoter,
+                    args.chain.unwrap_or_default()
+                ),
+                pool: pool.clone(),
+                quotes: vec![],
+            }
+        };
+        fixture.quotes.retain(|recorded| {
+            (recorded.zero_for_one, recorded.amount_in) != (quote.zero_for_one, quote.amount_in)
+        });
+        fixture.quotes.push(quote.clone());
+        fixture.save(&path)?;
+        println!(
+            "Recorded {} quotes in {}",
+            fixture.quotes.len(),
+            path.display()
+        );
+    }
+
+    // Recorded either way, a mismatch makes a failing test case
+    let swap = swaps[0];
+    ensure!(
+        swap.amount_out == quote.amount_out && swap.sqrt_price_x96 == quote.sqrt_price_x96_after,
+        "Simulation does not match the quoter"
+    );
+    Ok(())
+}
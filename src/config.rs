@@ -0,0 +1,741 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use async_nats::jetstream::stream::{RetentionPolicy, StorageType};
+use ethers::{
+    addressbook::{contract, Chain},
+    providers::{Provider, Ws},
+    types::{Address, H256},
+};
+use serde::Deserialize;
+
+use crate::{
+    abi::{read_abi, AbiResolver},
+    backoff::Backoff,
+    contracts::{Factory, Router},
+    format::Format,
+};
+
+/// Router versions the decoder understands
+const SUPPORTED_VERSIONS: [u8; 2] = [2, 3];
+
+/// Watcher configuration, loaded from a TOML file
+///
+/// String settings starting with `$` are read from the named environment variable,
+/// so endpoints and API keys can stay in `.env`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub abi: AbiConfig,
+    #[serde(default)]
+    pub watcher: WatcherConfig,
+    /// Where events go, each sink receiving those its filter matches.
+    /// Events are printed to stdout when no sink is configured
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+    /// Registry of V2 pairs, resolving the pools swaps trade against
+    pub pairs: Option<PairsConfig>,
+    /// Reserve tracking of the V2 pairs swaps trade against
+    pub reserves: Option<ReservesConfig>,
+    /// Sandwich opportunity detection on pending V2 swaps, needs `reserves`
+    pub sandwich: Option<SandwichConfig>,
+    /// Cross-pool arbitrage detection behind pending V2 swaps, needs `pairs` and `reserves`
+    pub arbitrage: Option<ArbitrageConfig>,
+    pub chains: Vec<ChainConfig>,
+}
+
+/// Where contract ABIs are looked up, and in which order
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AbiConfig {
+    pub sources: Vec<AbiSourceKind>,
+    /// Directory of vendored ABIs for the `directory` source
+    pub directory: PathBuf,
+    /// Repository URL or local mirror for the `sourcify` source
+    pub sourcify: String,
+    /// Directory of the `cache` source
+    pub cache_dir: PathBuf,
+    /// Seconds after which cached ABIs are refetched, 0 to keep them forever
+    pub cache_ttl: u64,
+}
+
+impl Default for AbiConfig {
+    fn default() -> Self {
+        Self {
+            sources: vec![
+                AbiSourceKind::Cache,
+                AbiSourceKind::Directory,
+                AbiSourceKind::Builtin,
+                AbiSourceKind::Explorer,
+                AbiSourceKind::Sourcify,
+            ],
+            directory: "abis".into(),
+            sourcify: "https://repo.sourcify.dev".to_string(),
+            cache_dir: ".cache".into(),
+            cache_ttl: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// How the watchers keep their node connections alive
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatcherConfig {
+    /// Milliseconds to wait before the first reconnect attempt
+    pub reconnect_delay: u64,
+    /// Upper bound, in milliseconds, of the doubling reconnect delay
+    pub max_reconnect_delay: u64,
+    /// Seconds without a pending transaction after which the subscription
+    /// is considered stalled and the connection is reopened, 0 to never
+    pub stall_timeout: u64,
+    /// Maximum number of concurrent `eth_getTransactionByHash` requests
+    pub fetch_concurrency: usize,
+    /// Retries of a fetch failing with a transient RPC error before the hash is skipped
+    pub fetch_retries: u32,
+    /// Pending hashes buffered between the subscription and the fetchers
+    pub queue_capacity: usize,
+    /// What to do with new hashes when the queue is full
+    pub overflow: OverflowPolicy,
+    /// Seconds between pipeline metric reports, 0 to disable them
+    pub metrics_interval: u64,
+    /// Seconds after which a router transaction not seen in any block is reported as dropped
+    pub inclusion_timeout: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Evict the oldest queued hash, favouring fresh transactions
+    #[default]
+    DropOldest,
+    /// Stop reading the subscription until the fetchers catch up
+    Block,
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        Self {
+            reconnect_delay: 500,
+            max_reconnect_delay: 60_000,
+            stall_timeout: 60,
+            fetch_concurrency: 32,
+            fetch_retries: 3,
+            queue_capacity: 4096,
+            overflow: OverflowPolicy::DropOldest,
+            metrics_interval: 60,
+            inclusion_timeout: 10 * 60,
+        }
+    }
+}
+
+impl WatcherConfig {
+    pub fn backoff(&self) -> Backoff {
+        Backoff::new(
+            Duration::from_millis(self.reconnect_delay),
+            Duration::from_millis(self.max_reconnect_delay),
+        )
+    }
+
+    pub fn stall_timeout(&self) -> Option<Duration> {
+        (self.stall_timeout > 0).then(|| Duration::from_secs(self.stall_timeout))
+    }
+
+    pub fn metrics_interval(&self) -> Option<Duration> {
+        (self.metrics_interval > 0).then(|| Duration::from_secs(self.metrics_interval))
+    }
+}
+
+/// Where the pair registry is kept and how it is backfilled
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PairsConfig {
+    /// Directory holding one registry file per chain
+    pub dir: PathBuf,
+    /// Blocks scanned by a single `eth_getLogs` request
+    pub batch_size: u64,
+}
+
+impl Default for PairsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(".cache/pairs"),
+            batch_size: 5000,
+        }
+    }
+}
+
+/// How V2 pair reserves are seeded and how much history is kept
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReservesConfig {
+    /// Blocks of reserve history kept per pair
+    pub history: u64,
+    /// Blocks a pair is followed after the last pending swap that traded against it
+    pub ttl: u64,
+    /// `getReserves` calls in flight while seeding
+    pub seed_concurrency: usize,
+}
+
+impl Default for ReservesConfig {
+    fn default() -> Self {
+        Self {
+            history: 64,
+            ttl: 300,
+            seed_concurrency: 16,
+        }
+    }
+}
+
+/// Costs and threshold of the sandwich opportunities reported
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandwichConfig {
+    /// Net profit an opportunity must reach, in wei of the wrapped native token
+    pub min_profit: u64,
+    /// Gas used by each of the front-run and back-run transactions
+    pub gas_per_swap: u64,
+    /// Priority fee per gas bid on top of the base fee, in wei
+    pub priority_fee: u64,
+}
+
+impl Default for SandwichConfig {
+    fn default() -> Self {
+        Self {
+            min_profit: 0,
+            gas_per_swap: 120_000,
+            priority_fee: 1_000_000_000,
+        }
+    }
+}
+
+/// How far arbitrage round trips are searched and which are reported
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArbitrageConfig {
+    /// Profit before gas a round trip must reach, in wei of the wrapped native token
+    pub min_profit: u64,
+    /// Round trips examined per pending swap
+    pub max_cycles: usize,
+}
+
+impl Default for ArbitrageConfig {
+    fn default() -> Self {
+        Self {
+            min_profit: 0,
+            max_cycles: 256,
+        }
+    }
+}
+
+/// An event destination and the events it receives
+#[derive(Clone, Debug, Deserialize)]
+pub struct SinkConfig {
+    #[serde(flatten)]
+    pub kind: SinkKind,
+    #[serde(default)]
+    pub filter: SinkFilter,
+    /// Events buffered for the sink before new ones are dropped
+    #[serde(default = "default_sink_capacity")]
+    pub capacity: usize,
+}
+
+fn default_sink_capacity() -> usize {
+    1024
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkKind {
+    /// Events printed on stdout
+    Stdout {
+        #[serde(default)]
+        format: Format,
+    },
+    /// Events appended to a file
+    File {
+        path: PathBuf,
+        #[serde(default)]
+        format: Format,
+    },
+    Nats(NatsConfig),
+    /// Requires the `scylla` feature
+    Scylla(ScyllaConfig),
+}
+
+/// Events a sink receives, empty lists matching everything
+///
+/// Router and function filters only apply to router events
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SinkFilter {
+    pub events: Vec<EventKind>,
+    /// Chain names, as in `[[chains]]`
+    pub chains: Vec<String>,
+    /// Router names, as in `[[chains.routers]]`
+    pub routers: Vec<String>,
+    /// Top-level router functions, e.g. `exactInputSingle`
+    pub functions: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Router,
+    Inclusion,
+    SandwichOpportunity,
+    ArbitrageOpportunity,
+}
+
+/// NATS server events are published to, as JSON on `{subject_prefix}.{chain}.{router}.{function}`,
+/// `{subject_prefix}.{chain}.inclusion.{status}`, `{subject_prefix}.{chain}.sandwich.{router}`
+/// and `{subject_prefix}.{chain}.arbitrage.{router}`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NatsConfig {
+    /// Server URL, e.g. `nats://localhost:4222`
+    pub url: String,
+    #[serde(default = "default_subject_prefix")]
+    pub subject_prefix: String,
+    pub token: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// `.creds` file holding a user JWT and NKey seed
+    pub credentials: Option<PathBuf>,
+    /// Persists events to a JetStream stream instead of publishing them fire-and-forget
+    pub jetstream: Option<JetStreamConfig>,
+}
+
+/// Stream capturing every watcher subject, created on startup if missing
+///
+/// Events carry their transaction hash as `Nats-Msg-Id`, so republished
+/// transactions are dropped by the server within `duplicate_window`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JetStreamConfig {
+    pub stream: String,
+    /// Seconds events are kept, 0 for no limit
+    pub max_age: u64,
+    /// Size of the stream in bytes before old events are discarded, 0 for no limit
+    pub max_bytes: u64,
+    /// Number of events kept before old ones are discarded, 0 for no limit
+    pub max_messages: u64,
+    /// `limits`, `interest` or `workqueue`
+    pub retention: RetentionPolicy,
+    /// `file` or `memory`
+    pub storage: StorageType,
+    pub replicas: usize,
+    /// Seconds during which an event for the same transaction is treated as a duplicate
+    pub duplicate_window: u64,
+}
+
+impl Default for JetStreamConfig {
+    fn default() -> Self {
+        Self {
+            stream: "UNISWAP".to_string(),
+            max_age: 7 * 24 * 60 * 60,
+            max_bytes: 0,
+            max_messages: 0,
+            retention: RetentionPolicy::Limits,
+            storage: StorageType::File,
+            replicas: 1,
+            duplicate_window: 2 * 60,
+        }
+    }
+}
+
+fn default_subject_prefix() -> String {
+    "uniswap".to_string()
+}
+
+impl NatsConfig {
+    pub fn url(&self) -> Result<String> {
+        resolve(&self.url)
+    }
+
+    pub fn token(&self) -> Result<Option<String>> {
+        self.token.as_deref().map(resolve).transpose()
+    }
+
+    pub fn user(&self) -> Result<Option<String>> {
+        self.user.as_deref().map(resolve).transpose()
+    }
+
+    pub fn password(&self) -> Result<Option<String>> {
+        self.password.as_deref().map(resolve).transpose()
+    }
+}
+
+/// Scylla cluster mempool history is written to
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "scylla"), allow(dead_code))]
+pub struct ScyllaConfig {
+    /// Contact points, e.g. `127.0.0.1:9042`
+    pub nodes: Vec<String>,
+    #[serde(default = "default_keyspace")]
+    pub keyspace: String,
+    /// Replication factor of the keyspace if the watcher has to create it
+    #[serde(default = "default_replication_factor")]
+    pub replication_factor: u32,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Rows queued before they are written, in one unlogged batch per partition
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Milliseconds after which a partial batch is written anyway
+    #[serde(default = "default_flush_interval")]
+    pub flush_interval: u64,
+}
+
+fn default_keyspace() -> String {
+    "uniswap_watcher".to_string()
+}
+
+fn default_replication_factor() -> u32 {
+    1
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_flush_interval() -> u64 {
+    1000
+}
+
+#[cfg_attr(not(feature = "scylla"), allow(dead_code))]
+impl ScyllaConfig {
+    pub fn user(&self) -> Result<Option<String>> {
+        self.user.as_deref().map(resolve).transpose()
+    }
+
+    pub fn password(&self) -> Result<Option<String>> {
+        self.password.as_deref().map(resolve).transpose()
+    }
+
+    pub fn nodes(&self) -> Result<Vec<String>> {
+        self.nodes.iter().map(|node| resolve(node)).collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AbiSourceKind {
+    /// ABIs previously fetched from a remote source
+    Cache,
+    /// ABIs of the well-known Uniswap contracts, compiled in
+    Builtin,
+    /// Vendored ABI files
+    Directory,
+    /// A Sourcify repository, over HTTP or on disk
+    Sourcify,
+    /// The chain's Etherscan-style explorer
+    Explorer,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainConfig {
+    /// Chain name as known to ethers, e.g. `mainnet`, `arbitrum` or `bsc`
+    pub name: String,
+    /// WebSocket endpoint of the chain's node
+    pub ws_url: String,
+    /// API key of the chain's Etherscan-style explorer, used to fetch missing ABIs
+    pub explorer_api_key: Option<String>,
+    /// Wrapped native token, e.g. WETH, in which MEV opportunities are valued.
+    /// Defaults to the WETH of ethers' address book, where the chain has one
+    pub wrapped_native: Option<String>,
+    /// Whether the node is asked to stream full pending transactions or only their hashes
+    #[serde(default)]
+    pub subscription: SubscriptionMode,
+    #[serde(default)]
+    pub factories: Vec<FactoryConfig>,
+    #[serde(default)]
+    pub routers: Vec<RouterConfig>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionMode {
+    /// Full transactions if the node supports them, hashes otherwise
+    #[default]
+    Auto,
+    /// `newPendingTransactions` with full transaction bodies (Geth, Erigon, Reth)
+    Full,
+    /// `newPendingTransactions` hashes, each fetched with `eth_getTransactionByHash`
+    Hashes,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FactoryConfig {
+    pub name: String,
+    pub address: String,
+    pub version: u8,
+    /// Block the factory was deployed at, where the pair backfill starts
+    #[serde(default)]
+    pub from_block: u64,
+    /// Keccak hash of the pair creation code, to compute V2 pair addresses locally
+    pub init_code_hash: Option<String>,
+    /// ABI file to use instead of looking it up from the ABI sources
+    pub abi: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouterConfig {
+    pub name: String,
+    pub address: String,
+    pub version: u8,
+    /// Names of the factories, from the same chain, whose pairs the router trades against
+    #[serde(default)]
+    pub factories: Vec<String>,
+    /// ABI file to use instead of looking it up from the ABI sources
+    pub abi: Option<PathBuf>,
+}
+
+impl Config {
+    /// Reads and validates the configuration at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read config {}", path.display()))?;
+        let config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Could not parse config {}", path.display()))?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks chain names, addresses, versions and factory references
+    pub fn validate(&self) -> Result<()> {
+        if self.watcher.fetch_concurrency == 0 || self.watcher.queue_capacity == 0 {
+            bail!("watcher fetch_concurrency and queue_capacity must be at least 1");
+        }
+        if self
+            .pairs
+            .as_ref()
+            .is_some_and(|pairs| pairs.batch_size == 0)
+        {
+            bail!("pairs batch_size must be at least 1");
+        }
+        if self
+            .reserves
+            .as_ref()
+            .is_some_and(|reserves| reserves.history == 0 || reserves.seed_concurrency == 0)
+        {
+            bail!("reserves history and seed_concurrency must be at least 1");
+        }
+        if self.sandwich.is_some() && self.reserves.is_none() {
+            bail!("sandwich detection needs reserve tracking, add a [reserves] section");
+        }
+        if let Some(arbitrage) = &self.arbitrage {
+            if self.pairs.is_none() || self.reserves.is_none() {
+                bail!("arbitrage detection needs the pair registry and reserve tracking, add [pairs] and [reserves] sections");
+            }
+            if arbitrage.max_cycles == 0 {
+                bail!("arbitrage max_cycles must be at least 1");
+            }
+        }
+        for sink in &self.sinks {
+            sink.validate()?;
+        }
+
+        let mut chains = HashSet::new();
+        for chain in &self.chains {
+            if !chains.insert(chain.chain()?) {
+                bail!("Chain {} is configured more than once", chain.name);
+            }
+            chain.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl SinkConfig {
+    fn validate(&self) -> Result<()> {
+        if self.capacity == 0 {
+            bail!("sink capacity must be at least 1");
+        }
+        for chain in &self.filter.chains {
+            chain
+                .parse::<Chain>()
+                .with_context(|| format!("Sink filter has unknown chain {}", chain))?;
+        }
+
+        match &self.kind {
+            SinkKind::Stdout { .. } | SinkKind::File { .. } => {}
+            SinkKind::Nats(nats) => {
+                if nats.user.is_some() != nats.password.is_some() {
+                    bail!("nats user and password must be set together");
+                }
+                if nats
+                    .jetstream
+                    .as_ref()
+                    .is_some_and(|jetstream| jetstream.stream.is_empty() || jetstream.replicas == 0)
+                {
+                    bail!("nats jetstream needs a stream name and at least one replica");
+                }
+            }
+            SinkKind::Scylla(scylla) => {
+                if !cfg!(feature = "scylla") {
+                    bail!("a scylla sink is configured but the watcher was built without the scylla feature");
+                }
+                if scylla.nodes.is_empty() || scylla.batch_size == 0 {
+                    bail!("scylla needs at least one node and a batch_size of at least 1");
+                }
+                if scylla.user.is_some() != scylla.password.is_some() {
+                    bail!("scylla user and password must be set together");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChainConfig {
+    pub fn chain(&self) -> Result<Chain> {
+        self.name
+            .parse()
+            .with_context(|| format!("Unknown chain {}", self.name))
+    }
+
+    pub fn ws_url(&self) -> Result<String> {
+        resolve(&self.ws_url)
+    }
+
+    pub fn explorer_api_key(&self) -> Result<Option<String>> {
+        self.explorer_api_key.as_deref().map(resolve).transpose()
+    }
+
+    pub fn wrapped_native(&self) -> Result<Option<Address>> {
+        match &self.wrapped_native {
+            Some(address) => parse_address(&self.name, "wrapped_native", address).map(Some),
+            None => Ok(contract("weth").and_then(|weth| weth.address(self.chain().ok()?))),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.wrapped_native()?;
+        let mut factories = HashSet::new();
+        for factory in &self.factories {
+            parse_address(&self.name, &factory.name, &factory.address)?;
+            check_version(&self.name, &factory.name, factory.version)?;
+            parse_hash(&self.name, &factory.name, factory.init_code_hash.as_deref())?;
+            if !factories.insert(factory.name.as_str()) {
+                bail!(
+                    "{}: factory {} is configured more than once",
+                    self.name,
+                    factory.name
+                );
+            }
+        }
+
+        for router in &self.routers {
+            parse_address(&self.name, &router.name, &router.address)?;
+            check_version(&self.name, &router.name, router.version)?;
+            for factory in &router.factories {
+                if !factories.contains(factory.as_str()) {
+                    bail!(
+                        "{}: router {} references unknown factory {}",
+                        self.name,
+                        router.name,
+                        factory
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds the routers of this chain, resolving any ABI not provided as a file
+    pub async fn routers(&self, abi: &AbiConfig, provider: Provider<Ws>) -> Result<Vec<Router>> {
+        let chain = self.chain()?;
+        let resolver = AbiResolver::new(abi, self.explorer_api_key()?).with_provider(provider);
+
+        let mut factories = Vec::with_capacity(self.factories.len());
+        for config in &self.factories {
+            let address = parse_address(&self.name, &config.name, &config.address)?;
+            factories.push(Factory {
+                address,
+                abi: load_abi(&resolver, chain, address, config.abi.as_deref()).await?,
+                name: config.name.clone(),
+                version: config.version,
+                from_block: config.from_block,
+                init_code_hash: parse_hash(
+                    &self.name,
+                    &config.name,
+                    config.init_code_hash.as_deref(),
+                )?,
+            });
+        }
+
+        let wrapped_native = self.wrapped_native()?;
+        let mut routers = Vec::with_capacity(self.routers.len());
+        for config in &self.routers {
+            let address = parse_address(&self.name, &config.name, &config.address)?;
+            routers.push(Router {
+                address,
+                abi: load_abi(&resolver, chain, address, config.abi.as_deref()).await?,
+                name: config.name.clone(),
+                version: config.version,
+                factory: config
+                    .factories
+                    .iter()
+                    .filter_map(|name| factories.iter().find(|factory| &factory.name == name))
+                    .cloned()
+                    .collect(),
+                wrapped_native,
+            });
+        }
+
+        Ok(routers)
+    }
+}
+
+async fn load_abi(
+    resolver: &AbiResolver,
+    chain: Chain,
+    address: Address,
+    path: Option<&Path>,
+) -> Result<ethers::abi::Abi> {
+    match path {
+        Some(path) => read_abi(path),
+        None => resolver.resolve(chain, address).await,
+    }
+}
+
+/// Reads `$NAME` values from the environment and returns any other value as is
+fn resolve(value: &str) -> Result<String> {
+    match value.strip_prefix('$') {
+        Some(name) => dotenv::var(name).with_context(|| format!("{} missing", name)),
+        None => Ok(value.to_string()),
+    }
+}
+
+fn parse_address(chain: &str, name: &str, address: &str) -> Result<Address> {
+    address
+        .parse()
+        .with_context(|| format!("{}: {} has malformed address {}", chain, name, address))
+}
+
+fn parse_hash(chain: &str, name: &str, hash: Option<&str>) -> Result<Option<H256>> {
+    hash.map(|hash| {
+        hash.parse()
+            .with_context(|| format!("{}: {} has malformed init code hash {}", chain, name, hash))
+    })
+    .transpose()
+}
+
+fn check_version(chain: &str, name: &str, version: u8) -> Result<()> {
+    if !SUPPORTED_VERSIONS.contains(&version) {
+        bail!("{}: {} has unsupported version {}", chain, name, version);
+    }
+    Ok(())
+}
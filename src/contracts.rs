@@ -0,0 +1,138 @@
+use ethers::{
+    abi::Abi,
+    types::{Address, H256},
+    utils::{get_create2_address_from_hash, keccak256},
+};
+
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct Factory {
+    pub address: Address,
+    pub abi: Abi,
+    pub name: String,
+    pub version: u8,
+    /// Block the factory was deployed at
+    pub from_block: u64,
+    /// Hash of the pair creation code, from which V2 pair addresses are derived
+    pub init_code_hash: Option<H256>,
+}
+
+impl Factory {
+    /// Address of the pair trading `a` against `b`, computed the way the factory
+    /// deploys it with CREATE2. `None` without an init code hash
+    ///
+    /// The address is derived whether or not the pair has been created yet
+    pub fn pair_address(&self, a: Address, b: Address) -> Option<Address> {
+        let init_code_hash = self.init_code_hash?;
+        let (token0, token1) = sort_tokens(a, b);
+        let salt = keccak256([token0.as_bytes(), token1.as_bytes()].concat());
+        Some(get_create2_address_from_hash(
+            self.address,
+            salt,
+            init_code_hash,
+        ))
+    }
+
+    /// Pairs a swap along `path` trades against, one per hop
+    pub fn path_pairs(&self, path: &[Address]) -> Option<Vec<Address>> {
+        path.windows(2)
+            .map(|hop| self.pair_address(hop[0], hop[1]))
+            .collect()
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct Router {
+    pub address: Address,
+    pub abi: Abi,
+    pub name: String,
+    pub version: u8,
+    pub factory: Vec<Factory>,
+    /// Wrapped native token of the chain, which the router wraps ETH sent along into
+    pub wrapped_native: Option<Address>,
+}
+
+/// Orders two tokens the way V2 factories do, by address
+pub fn sort_tokens(a: Address, b: Address) -> (Address, Address) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const USDT: &str = "0xdAC17F958D2ee523a2206206994597C13D831ec7";
+    const DAI: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
+    const WBTC: &str = "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599";
+
+    fn uniswap() -> Factory {
+        Factory {
+            address: address("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+            abi: Abi::default(),
+            name: "Uniswap V2".to_string(),
+            version: 2,
+            from_block: 10000835,
+            init_code_hash: Some(
+                "0x96e8ac4277198ff8b6f785478aa9a39f403cb768dd02cbee326c3e7da348845f"
+                    .parse()
+                    .unwrap(),
+            ),
+        }
+    }
+
+    fn address(address: &str) -> Address {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn computes_known_mainnet_pairs() {
+        let factory = uniswap();
+        let pairs = [
+            (WETH, USDC, "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc"),
+            (WETH, USDT, "0x0d4a11d5EEaaC28EC3F61d100daF4d40471f1852"),
+            (DAI, WETH, "0xA478c2975Ab1Ea89e8196811F51A7B7Ade33eB11"),
+            (WBTC, WETH, "0xBb2b8038a1640196FbE3e38816F3e67Cba72D940"),
+        ];
+        for (a, b, pair) in pairs {
+            assert_eq!(
+                factory.pair_address(address(a), address(b)),
+                Some(address(pair))
+            );
+            // Token order does not matter
+            assert_eq!(
+                factory.pair_address(address(b), address(a)),
+                Some(address(pair))
+            );
+        }
+    }
+
+    #[test]
+    fn computes_one_pair_per_hop() {
+        let path = [address(USDC), address(WETH), address(DAI)];
+        assert_eq!(
+            uniswap().path_pairs(&path),
+            Some(vec![
+                address("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc"),
+                address("0xA478c2975Ab1Ea89e8196811F51A7B7Ade33eB11"),
+            ])
+        );
+        assert_eq!(uniswap().path_pairs(&path[..1]), Some(vec![]));
+    }
+
+    #[test]
+    fn needs_an_init_code_hash() {
+        let factory = Factory {
+            init_code_hash: None,
+            ..uniswap()
+        };
+        assert_eq!(factory.pair_address(address(WETH), address(USDC)), None);
+        assert_eq!(factory.path_pairs(&[address(WETH), address(USDC)]), None);
+    }
+}
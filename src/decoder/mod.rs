@@ -0,0 +1,127 @@
+use anyhow::{bail, Context, Result};
+use ethers::{
+    abi::{Abi, Function, Param, Token},
+    types::{Address, Transaction, U256},
+};
+
+use crate::{contracts::Router, model::Intent};
+
+mod universal;
+mod v2;
+mod v3;
+
+/// The intents carried by a single router call
+#[derive(Clone, Debug)]
+pub struct DecodedCall {
+    /// Name of the top-level router function, e.g. `multicall`
+    pub function: String,
+    pub intents: Vec<Intent>,
+}
+
+/// Looks up the ABI function whose selector matches the first 4 bytes of `input`
+pub fn find_function<'a>(abi: &'a Abi, input: &[u8]) -> Option<&'a Function> {
+    let selector = input.get(..4)?;
+    abi.functions()
+        .find(|function| function.short_signature() == selector)
+}
+
+/// Decodes the calldata of a transaction sent to `router`
+/// Returns `None` if the call contains no swap or liquidity operation
+pub fn decode(router: &Router, tx: &Transaction) -> Result<Option<DecodedCall>> {
+    let Some(function) = find_function(&router.abi, &tx.input) else {
+        return Ok(None);
+    };
+
+    let intents = match router.version {
+        2 => v2::decode(function, &tx.input, tx.value)?
+            .into_iter()
+            .collect(),
+        // The Universal Router routes through both V2 and V3 pools
+        3 if function.name == "execute" => universal::decode(router, function, tx)?,
+        3 => v3::decode(router, function, &tx.input, tx.value)?,
+        version => bail!("Unsupported router version {}", version),
+    };
+    if intents.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(DecodedCall {
+        function: function.name.clone(),
+        intents,
+    }))
+}
+
+fn address(tokens: &[Token], index: usize) -> Result<Address> {
+    tokens
+        .get(index)
+        .cloned()
+        .and_then(Token::into_address)
+        .with_context(|| format!("expected address argument at {}", index))
+}
+
+fn uint(tokens: &[Token], index: usize) -> Result<U256> {
+    tokens
+        .get(index)
+        .cloned()
+        .and_then(Token::into_uint)
+        .with_context(|| format!("expected uint argument at {}", index))
+}
+
+fn addresses(tokens: &[Token], index: usize) -> Result<Vec<Address>> {
+    tokens
+        .get(index)
+        .cloned()
+        .and_then(Token::into_array)
+        .and_then(|path| path.into_iter().map(Token::into_address).collect())
+        .with_context(|| format!("expected address[] argument at {}", index))
+}
+
+fn bytes(tokens: &[Token], index: usize) -> Result<Vec<u8>> {
+    tokens
+        .get(index)
+        .cloned()
+        .and_then(Token::into_bytes)
+        .with_context(|| format!("expected bytes argument at {}", index))
+}
+
+/// Decoded function arguments, addressable by their ABI parameter names
+struct Args<'a> {
+    params: &'a [Param],
+    tokens: Vec<Token>,
+}
+
+impl<'a> Args<'a> {
+    fn decode(function: &'a Function, input: &[u8]) -> Result<Self> {
+        Ok(Self {
+            params: &function.inputs,
+            tokens: function.decode_input(&input[4..])?,
+        })
+    }
+
+    fn get(&self, name: &str) -> Option<&Token> {
+        self.params
+            .iter()
+            .position(|param| param.name == name)
+            .and_then(|index| self.tokens.get(index))
+    }
+
+    /// Returns the first of `names` present as a uint argument
+    fn uint(&self, names: &[&str]) -> Option<U256> {
+        names
+            .iter()
+            .find_map(|name| self.get(name).cloned()?.into_uint())
+    }
+
+    fn address(&self, name: &str) -> Option<Address> {
+        self.get(name).cloned()?.into_address()
+    }
+
+    fn addresses(&self, name: &str) -> Option<Vec<Address>> {
+        self.get(name)
+            .cloned()?
+            .into_array()?
+            .into_iter()
+            .map(Token::into_address)
+            .collect()
+    }
+}
@@ -0,0 +1,637 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use ethers::{
+    abi::{decode as abi_decode, Function, ParamType, Token},
+    types::{Address, Transaction, U256},
+};
+
+use super::{address, addresses, bytes, uint, v3::decode_path};
+use crate::{
+    contracts::Router,
+    model::{Intent, SwapDirection, SwapIntent, SwapKind},
+};
+
+// Command types from the Universal Router `Commands` library
+const V3_SWAP_EXACT_IN: u8 = 0x00;
+const V3_SWAP_EXACT_OUT: u8 = 0x01;
+const PERMIT2_TRANSFER_FROM: u8 = 0x02;
+const PERMIT2_PERMIT_BATCH: u8 = 0x03;
+const SWEEP: u8 = 0x04;
+const TRANSFER: u8 = 0x05;
+const PAY_PORTION: u8 = 0x06;
+const V2_SWAP_EXACT_IN: u8 = 0x08;
+const V2_SWAP_EXACT_OUT: u8 = 0x09;
+const PERMIT2_PERMIT: u8 = 0x0a;
+const WRAP_ETH: u8 = 0x0b;
+const UNWRAP_WETH: u8 = 0x0c;
+const PERMIT2_TRANSFER_FROM_BATCH: u8 = 0x0d;
+const BALANCE_CHECK_ERC20: u8 = 0x0e;
+const EXECUTE_SUB_PLAN: u8 = 0x21;
+
+/// Bits of a command byte holding its type; the top bit flags reverts as allowed
+const COMMAND_TYPE_MASK: u8 = 0x3f;
+
+// Recipients from the Universal Router `Constants` library
+const MSG_SENDER: u64 = 1;
+const ADDRESS_THIS: u64 = 2;
+
+/// `Constants.CONTRACT_BALANCE`, an amount standing for the router's whole balance
+fn contract_balance() -> U256 {
+    U256::one() << 255
+}
+
+/// Decodes `execute(bytes,bytes[])` and `execute(bytes,bytes[],uint256)` calls
+/// to the Universal Router by walking their command stream
+pub fn decode(router: &Router, function: &Function, tx: &Transaction) -> Result<Vec<Intent>> {
+    let args = function.decode_input(&tx.input[4..])?;
+    let mut plan = Plan {
+        sender: tx.from,
+        router: router.address,
+        value: tx.value,
+        wrapped_native: router.wrapped_native,
+        deadline: args.get(2).cloned().and_then(Token::into_uint),
+        ..Default::default()
+    };
+    plan.walk(&bytes(&args, 0)?, bytes_array(&args, 1)?)?;
+
+    // Native legs are expressed as separate wrap/unwrap commands around the swaps
+    let direction = match (plan.wraps_eth, plan.unwraps_eth) {
+        (true, false) => Some(SwapDirection::EthToToken),
+        (false, true) => Some(SwapDirection::TokenToEth),
+        _ => None,
+    };
+
+    Ok(plan
+        .swaps
+        .into_iter()
+        .map(|mut swap| {
+            if let Some(direction) = direction {
+                swap.direction = direction;
+                swap.native_eth = true;
+            }
+            Intent::Swap(swap)
+        })
+        .collect())
+}
+
+/// Swaps and ETH wrapping collected from a command stream
+///
+/// Commands can spend what earlier ones left with the router or sent ahead to a
+/// pair, so the amounts known to be there are followed along the stream
+#[derive(Default)]
+struct Plan {
+    sender: Address,
+    router: Address,
+    value: U256,
+    wrapped_native: Option<Address>,
+    deadline: Option<U256>,
+    swaps: Vec<SwapIntent>,
+    wraps_eth: bool,
+    unwraps_eth: bool,
+    /// Amount of each token held by the router, where known
+    held: HashMap<Address, U256>,
+    /// Amount of each token paid to a pair ahead of its swap, where known
+    paid: HashMap<Address, U256>,
+}
+
+impl Plan {
+    fn walk(&mut self, commands: &[u8], inputs: Vec<Vec<u8>>) -> Result<()> {
+        if commands.len() != inputs.len() {
+            bail!("{} commands but {} inputs", commands.len(), inputs.len());
+        }
+
+        for (command, input) in commands.iter().zip(inputs) {
+            match command & COMMAND_TYPE_MASK {
+                V3_SWAP_EXACT_IN => self.swap(3, SwapKind::ExactIn, &input)?,
+                V3_SWAP_EXACT_OUT => self.swap(3, SwapKind::ExactOut, &input)?,
+                V2_SWAP_EXACT_IN => self.swap(2, SwapKind::ExactIn, &input)?,
+                V2_SWAP_EXACT_OUT => self.swap(2, SwapKind::ExactOut, &input)?,
+                WRAP_ETH => self.wrap_eth(&input)?,
+                UNWRAP_WETH => self.unwraps_eth = true,
+                PERMIT2_TRANSFER_FROM | TRANSFER => self.transfer(*command, &input)?,
+                EXECUTE_SUB_PLAN => {
+                    let args = abi_decode(
+                        &[
+                            ParamType::Bytes,
+                            ParamType::Array(Box::new(ParamType::Bytes)),
+                        ],
+                        &input,
+                    )?;
+                    self.walk(&bytes(&args, 0)?, bytes_array(&args, 1)?)?;
+                }
+                // Token movements and approvals carry no swap intent
+                PERMIT2_PERMIT_BATCH
+                | SWEEP
+                | PAY_PORTION
+                | PERMIT2_PERMIT
+                | PERMIT2_TRANSFER_FROM_BATCH
+                | BALANCE_CHECK_ERC20 => {}
+                // NFT marketplace commands
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the inputs shared by the V2 and V3 swap commands:
+    /// `(address recipient, uint256 amount, uint256 limit, path, bool payerIsUser)`
+    fn swap(&mut self, version: u8, kind: SwapKind, input: &[u8]) -> Result<()> {
+        let path_type = match version {
+            2 => ParamType::Array(Box::new(ParamType::Address)),
+            _ => ParamType::Bytes,
+        };
+        let args = abi_decode(
+            &[
+                ParamType::Address,
+                ParamType::Uint(256),
+                ParamType::Uint(256),
+                path_type,
+                ParamType::Bool,
+            ],
+            input,
+        )?;
+
+        let (path, fees) = match version {
+            2 => (addresses(&args, 3)?, vec![]),
+            _ => {
+                let (mut path, mut fees) = decode_path(&bytes(&args, 3)?)?;
+                // Exact-output V3 paths are encoded from the output token backwards
+                if kind == SwapKind::ExactOut {
+                    path.reverse();
+                    fees.reverse();
+                }
+                (path, fees)
+            }
+        };
+        let (Some(&token_in), Some(&token_out)) = (path.first(), path.last()) else {
+            bail!("Empty swap path");
+        };
+
+        let recipient = self.recipient(address(&args, 0)?);
+        let payer_is_user = args
+            .get(4)
+            .cloned()
+            .and_then(Token::into_bool)
+            .context("expected bool argument at 4")?;
+        let (amount, limit) = (uint(&args, 1)?, uint(&args, 2)?);
+        let (amount_in, amount_out) = match kind {
+            SwapKind::ExactIn => (self.spend(version, token_in, amount, payer_is_user), limit),
+            SwapKind::ExactOut => {
+                // The router pays whatever the pools ask, up to the limit
+                if !payer_is_user {
+                    self.held.remove(&token_in);
+                }
+                (Some(limit), amount)
+            }
+        };
+        // Only exact-output swaps fix what they leave for the following commands
+        self.credit(
+            recipient,
+            token_out,
+            (kind == SwapKind::ExactOut).then_some(amount),
+        );
+
+        let function = match (version, kind) {
+            (2, SwapKind::ExactIn) => "V2_SWAP_EXACT_IN",
+            (2, SwapKind::ExactOut) => "V2_SWAP_EXACT_OUT",
+            (_, SwapKind::ExactIn) => "V3_SWAP_EXACT_IN",
+            (_, SwapKind::ExactOut) => "V3_SWAP_EXACT_OUT",
+        };
+
+        self.swaps.push(SwapIntent {
+            function: function.to_string(),
+            kind,
+            direction: SwapDirection::TokenToToken,
+            native_eth: false,
+            path,
+            amount_in,
+            amount_out,
+            recipient: Some(recipient),
+            deadline: self.deadline,
+            fee_on_transfer: false,
+            version,
+            fees,
+            pools: vec![],
+            reserves: vec![],
+            quote: None,
+        });
+
+        Ok(())
+    }
+
+    /// Input of an exact-input swap, resolving the amounts standing for tokens
+    /// the router holds or already paid to the first pair
+    fn spend(
+        &mut self,
+        version: u8,
+        token: Address,
+        amount: U256,
+        payer_is_user: bool,
+    ) -> Option<U256> {
+        // `Constants.ALREADY_PAID`
+        if version == 2 && amount.is_zero() {
+            return self.paid.remove(&token);
+        }
+        if amount == contract_balance() {
+            return self.held.remove(&token);
+        }
+        if !payer_is_user {
+            // The rest is left for the other legs of a split route
+            if let Some(held) = self.held.get_mut(&token) {
+                *held = held.saturating_sub(amount);
+            }
+        }
+        Some(amount)
+    }
+
+    /// Decodes `WRAP_ETH`: `(address recipient, uint256 amountMin)`
+    fn wrap_eth(&mut self, input: &[u8]) -> Result<()> {
+        self.wraps_eth = true;
+        let args = abi_decode(&[ParamType::Address, ParamType::Uint(256)], input)?;
+        let amount = uint(&args, 1)?;
+        // The router wraps all the ETH it holds, which is what the call sent
+        let amount = if amount == contract_balance() {
+            self.value
+        } else {
+            amount
+        };
+        if let Some(wrapped_native) = self.wrapped_native {
+            let recipient = self.recipient(address(&args, 0)?);
+            self.credit(recipient, wrapped_native, Some(amount));
+        }
+        Ok(())
+    }
+
+    /// Decodes `PERMIT2_TRANSFER_FROM` and `TRANSFER`:
+    /// `(address token, address recipient, uint256 amount)`, the former moving
+    /// tokens from the sender and the latter from the router
+    fn transfer(&mut self, command: u8, input: &[u8]) -> Result<()> {
+        let amount_type = match command & COMMAND_TYPE_MASK {
+            PERMIT2_TRANSFER_FROM => ParamType::Uint(160),
+            _ => ParamType::Uint(256),
+        };
+        let args = abi_decode(
+            &[ParamType::Address, ParamType::Address, amount_type],
+            input,
+        )?;
+        let (token, amount) = (address(&args, 0)?, uint(&args, 2)?);
+
+        let amount = match command & COMMAND_TYPE_MASK {
+            PERMIT2_TRANSFER_FROM => Some(amount),
+            _ if amount == contract_balance() => self.held.remove(&token),
+            _ => {
+                if let Some(held) = self.held.get_mut(&token) {
+                    *held = held.saturating_sub(amount);
+                }
+                Some(amount)
+            }
+        };
+        let recipient = self.recipient(address(&args, 1)?);
+        self.credit(recipient, token, amount);
+        Ok(())
+    }
+
+    /// Records `amount` of `token` reaching `recipient`, `None` standing for an
+    /// amount the calldata does not fix
+    fn credit(&mut self, recipient: Address, token: Address, amount: Option<U256>) {
+        let balances = if recipient == self.router {
+            &mut self.held
+        } else if recipient == self.sender {
+            return;
+        } else {
+            &mut self.paid
+        };
+        match amount {
+            Some(amount) => {
+                let balance = balances.entry(token).or_default();
+                *balance = balance.saturating_add(amount);
+            }
+            None => {
+                balances.remove(&token);
+            }
+        }
+    }
+
+    /// Resolves the recipients standing for the sender and the router itself
+    fn recipient(&self, recipient: Address) -> Address {
+        if recipient == Address::from_low_u64_be(MSG_SENDER) {
+            self.sender
+        } else if recipient == Address::from_low_u64_be(ADDRESS_THIS) {
+            self.router
+        } else {
+            recipient
+        }
+    }
+}
+
+fn bytes_array(tokens: &[Token], index: usize) -> Result<Vec<Vec<u8>>> {
+    tokens
+        .get(index)
+        .cloned()
+        .and_then(Token::into_array)
+        .and_then(|inputs| inputs.into_iter().map(Token::into_bytes).collect())
+        .with_context(|| format!("expected bytes[] argument at {}", index))
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::abi::{encode, parse_abi};
+
+    use super::*;
+
+    const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const DAI: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
+    const USDC_WETH: &str = "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc";
+
+    fn address(address: &str) -> Address {
+        address.parse().unwrap()
+    }
+
+    fn sender() -> Address {
+        Address::repeat_byte(0xaa)
+    }
+
+    fn router() -> Router {
+        Router {
+            address: address("0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD"),
+            abi: parse_abi(&[
+                "function execute(bytes commands, bytes[] inputs, uint256 deadline) external payable",
+            ])
+            .unwrap(),
+            name: "Uniswap Universal Router".to_string(),
+            version: 3,
+            factory: vec![],
+            wrapped_native: Some(address(WETH)),
+        }
+    }
+
+    /// Decodes the swaps of an `execute` call sending `value`
+    fn execute(commands: &[u8], inputs: Vec<Vec<u8>>, value: U256) -> Result<Vec<SwapIntent>> {
+        let router = router();
+        let function = router.abi.function("execute").unwrap();
+        let input = function
+            .encode_input(&[
+                Token::Bytes(commands.to_vec()),
+                Token::Array(inputs.into_iter().map(Token::Bytes).collect()),
+                Token::Uint(1_700_000_000.into()),
+            ])
+            .unwrap();
+        let tx = Transaction {
+            from: sender(),
+            to: Some(router.address),
+            value,
+            input: input.into(),
+            ..Default::default()
+        };
+        Ok(decode(&router, function, &tx)?
+            .into_iter()
+            .filter_map(|intent| match intent {
+                Intent::Swap(swap) => Some(swap),
+                Intent::Liquidity(_) => None,
+            })
+            .collect())
+    }
+
+    fn v2_swap(recipient: u64, amount: U256, limit: U256, path: &[&str], user: bool) -> Vec<u8> {
+        encode(&[
+            Token::Address(Address::from_low_u64_be(recipient)),
+            Token::Uint(amount),
+            Token::Uint(limit),
+            Token::Array(path.iter().map(|t| Token::Address(address(t))).collect()),
+            Token::Bool(user),
+        ])
+    }
+
+    /// A V3 swap command along `tokens`, packed in the order given
+    fn v3_swap(
+        recipient: u64,
+        amount: U256,
+        limit: U256,
+        tokens: &[&str],
+        fees: &[u32],
+        user: bool,
+    ) -> Vec<u8> {
+        let mut path = address(tokens[0]).as_bytes().to_vec();
+        for (token, fee) in tokens[1..].iter().zip(fees) {
+            path.extend_from_slice(&fee.to_be_bytes()[1..]);
+            path.extend_from_slice(address(token).as_bytes());
+        }
+        encode(&[
+            Token::Address(Address::from_low_u64_be(recipient)),
+            Token::Uint(amount),
+            Token::Uint(limit),
+            Token::Bytes(path),
+            Token::Bool(user),
+        ])
+    }
+
+    fn wrap_eth(recipient: u64, amount: U256) -> Vec<u8> {
+        encode(&[
+            Token::Address(Address::from_low_u64_be(recipient)),
+            Token::Uint(amount),
+        ])
+    }
+
+    #[test]
+    fn decodes_v2_exact_input() {
+        let swaps = execute(
+            &[V2_SWAP_EXACT_IN],
+            vec![v2_swap(
+                MSG_SENDER,
+                U256::exp10(9),
+                U256::exp10(17),
+                &[USDC, WETH],
+                true,
+            )],
+            U256::zero(),
+        )
+        .unwrap();
+
+        assert_eq!(swaps.len(), 1);
+        let swap = &swaps[0];
+        assert_eq!(swap.function, "V2_SWAP_EXACT_IN");
+        assert_eq!(swap.kind, SwapKind::ExactIn);
+        assert_eq!(swap.direction, SwapDirection::TokenToToken);
+        assert_eq!(swap.version, 2);
+        assert_eq!(swap.path, vec![address(USDC), address(WETH)]);
+        assert_eq!(swap.amount_in, Some(U256::exp10(9)));
+        assert_eq!(swap.amount_out, U256::exp10(17));
+        assert_eq!(swap.recipient, Some(sender()));
+        assert_eq!(swap.deadline, Some(1_700_000_000.into()));
+        assert!(!swap.fee_on_transfer);
+    }
+
+    #[test]
+    fn masks_the_allow_revert_flag() {
+        let swaps = execute(
+            &[0x80 | V2_SWAP_EXACT_IN, 0x80 | PERMIT2_PERMIT],
+            vec![
+                v2_swap(
+                    MSG_SENDER,
+                    U256::exp10(9),
+                    U256::exp10(17),
+                    &[USDC, WETH],
+                    true,
+                ),
+                vec![],
+            ],
+            U256::zero(),
+        )
+        .unwrap();
+
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].function, "V2_SWAP_EXACT_IN");
+    }
+
+    #[test]
+    fn reverses_v3_exact_output_paths() {
+        // Buys DAI with WETH through USDC, the path packed from DAI backwards
+        let swaps = execute(
+            &[V3_SWAP_EXACT_OUT],
+            vec![v3_swap(
+                MSG_SENDER,
+                U256::exp10(21),
+                U256::exp10(18),
+                &[DAI, USDC, WETH],
+                &[100, 500],
+                true,
+            )],
+            U256::zero(),
+        )
+        .unwrap();
+
+        let swap = &swaps[0];
+        assert_eq!(swap.function, "V3_SWAP_EXACT_OUT");
+        assert_eq!(swap.kind, SwapKind::ExactOut);
+        assert_eq!(swap.version, 3);
+        assert_eq!(swap.path, vec![address(WETH), address(USDC), address(DAI)]);
+        assert_eq!(swap.fees, vec![500, 100]);
+        assert_eq!(swap.amount_in, Some(U256::exp10(18)));
+        assert_eq!(swap.amount_out, U256::exp10(21));
+    }
+
+    #[test]
+    fn spends_the_wrapped_eth() {
+        let value = U256::exp10(18);
+        let swaps = execute(
+            &[WRAP_ETH, V3_SWAP_EXACT_IN],
+            vec![
+                wrap_eth(ADDRESS_THIS, contract_balance()),
+                v3_swap(
+                    MSG_SENDER,
+                    contract_balance(),
+                    U256::exp10(9),
+                    &[WETH, USDC],
+                    &[500],
+                    false,
+                ),
+            ],
+            value,
+        )
+        .unwrap();
+
+        let swap = &swaps[0];
+        assert_eq!(swap.direction, SwapDirection::EthToToken);
+        assert!(swap.native_eth);
+        assert_eq!(swap.amount_in, Some(value));
+        assert_eq!(swap.recipient, Some(sender()));
+    }
+
+    #[test]
+    fn splits_the_wrapped_eth_between_routes() {
+        let value = U256::exp10(18);
+        let first = U256::exp10(17) * 6;
+        let swaps = execute(
+            &[WRAP_ETH, V3_SWAP_EXACT_IN, V2_SWAP_EXACT_IN],
+            vec![
+                wrap_eth(ADDRESS_THIS, value),
+                v3_swap(
+                    MSG_SENDER,
+                    first,
+                    U256::zero(),
+                    &[WETH, USDC],
+                    &[500],
+                    false,
+                ),
+                v2_swap(
+                    MSG_SENDER,
+                    contract_balance(),
+                    U256::zero(),
+                    &[WETH, USDC],
+                    false,
+                ),
+            ],
+            value,
+        )
+        .unwrap();
+
+        assert_eq!(swaps[0].amount_in, Some(first));
+        assert_eq!(swaps[1].amount_in, Some(value - first));
+    }
+
+    #[test]
+    fn resolves_inputs_paid_to_the_pair() {
+        let transfer = encode(&[
+            Token::Address(address(USDC)),
+            Token::Address(address(USDC_WETH)),
+            Token::Uint(U256::exp10(9)),
+        ]);
+        let swaps = execute(
+            &[PERMIT2_TRANSFER_FROM, V2_SWAP_EXACT_IN],
+            vec![
+                transfer,
+                v2_swap(MSG_SENDER, U256::zero(), U256::one(), &[USDC, WETH], true),
+            ],
+            U256::zero(),
+        )
+        .unwrap();
+
+        assert_eq!(swaps[0].amount_in, Some(U256::exp10(9)));
+    }
+
+    #[test]
+    fn leaves_unknown_balances_unresolved() {
+        // The router's USDC comes out of an exact-input swap of unknown output
+        let swaps = execute(
+            &[V3_SWAP_EXACT_IN, V3_SWAP_EXACT_IN],
+            vec![
+                v3_swap(
+                    ADDRESS_THIS,
+                    U256::exp10(18),
+                    U256::one(),
+                    &[WETH, USDC],
+                    &[500],
+                    true,
+                ),
+                v3_swap(
+                    MSG_SENDER,
+                    contract_balance(),
+                    U256::one(),
+                    &[USDC, DAI],
+                    &[100],
+                    false,
+                ),
+            ],
+            U256::zero(),
+        )
+        .unwrap();
+
+        assert_eq!(swaps[0].recipient, Some(router().address));
+        assert_eq!(swaps[1].amount_in, None);
+    }
+
+    #[test]
+    fn rejects_truncated_inputs() {
+        let swap = v2_swap(MSG_SENDER, U256::exp10(9), U256::one(), &[USDC, WETH], true);
+        assert!(execute(
+            &[V2_SWAP_EXACT_IN, UNWRAP_WETH],
+            vec![swap.clone()],
+            U256::zero()
+        )
+        .is_err());
+        assert!(execute(&[V2_SWAP_EXACT_IN], vec![swap[..64].to_vec()], U256::zero()).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::{Context, Result};
+use ethers::{abi::Function, types::U256};
+
+use super::Args;
+use crate::model::{Intent, LiquidityAction, LiquidityIntent, SwapDirection, SwapIntent, SwapKind};
+
+/// Decodes a call to a Uniswap V2 style router (`IUniswapV2Router01`/`IUniswapV2Router02`)
+///
+/// Arguments are looked up by their ABI names so every `swap*`, `addLiquidity*`
+/// and `removeLiquidity*` variant, including the `WithPermit` and
+/// `SupportingFeeOnTransferTokens` ones, shares the same mapping
+pub fn decode(function: &Function, input: &[u8], value: U256) -> Result<Option<Intent>> {
+    let name = function.name.as_str();
+
+    let intent = if name.starts_with("swap") {
+        Intent::Swap(decode_swap(name, &Args::decode(function, input)?, value)?)
+    } else if name.starts_with("addLiquidity") || name.starts_with("removeLiquidity") {
+        Intent::Liquidity(decode_liquidity(
+            name,
+            &Args::decode(function, input)?,
+            value,
+        )?)
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(intent))
+}
+
+fn decode_swap(name: &str, args: &Args, value: U256) -> Result<SwapIntent> {
+    let direction = if name.contains("ETHFor") {
+        SwapDirection::EthToToken
+    } else if name.contains("ForETH") || name.contains("ForExactETH") {
+        SwapDirection::TokenToEth
+    } else {
+        SwapDirection::TokenToToken
+    };
+
+    // ETH-in swaps spend msg.value instead of taking an input amount
+    let amount_in = match direction {
+        SwapDirection::EthToToken => value,
+        _ => args
+            .uint(&["amountIn", "amountInMax"])
+            .context("missing input amount")?,
+    };
+
+    Ok(SwapIntent {
+        function: name.to_string(),
+        kind: if name.contains("ForExact") {
+            SwapKind::ExactOut
+        } else {
+            SwapKind::ExactIn
+        },
+        direction,
+        native_eth: direction != SwapDirection::TokenToToken,
+        path: args.addresses("path").context("missing path")?,
+        amount_in: Some(amount_in),
+        amount_out: args
+            .uint(&["amountOutMin", "amountOut"])
+            .context("missing output amount")?,
+        recipient: args.address("to"),
+        deadline: args.uint(&["deadline"]),
+        fee_on_transfer: name.ends_with("SupportingFeeOnTransferTokens"),
+        version: 2,
+        fees: vec![],
+        pools: vec![],
+        reserves: vec![],
+        quote: None,
+    })
+}
+
+fn decode_liquidity(name: &str, args: &Args, value: U256) -> Result<LiquidityIntent> {
+    let action = if name.starts_with("addLiquidity") {
+        LiquidityAction::Add
+    } else {
+        LiquidityAction::Remove
+    };
+    let native_eth = name.contains("ETH");
+
+    // The ETH side of addLiquidityETH is offered as msg.value
+    let amount_b_desired = args
+        .uint(&["amountBDesired"])
+        .or((native_eth && action == LiquidityAction::Add).then_some(value));
+
+    Ok(LiquidityIntent {
+        function: name.to_string(),
+        action,
+        native_eth,
+        token_a: args
+            .address("tokenA")
+            .or_else(|| args.address("token"))
+            .context("missing token")?,
+        token_b: args.address("tokenB"),
+        amount_a_desired: args.uint(&["amountADesired", "amountTokenDesired"]),
+        amount_b_desired,
+        amount_a_min: args
+            .uint(&["amountAMin", "amountTokenMin"])
+            .context("missing minimum token amount")?,
+        amount_b_min: args
+            .uint(&["amountBMin", "amountETHMin"])
+            .context("missing minimum pair amount")?,
+        liquidity: args.uint(&["liquidity"]),
+        recipient: args.address("to"),
+        deadline: args.uint(&["deadline"]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        addressbook::Chain,
+        types::{Address, Transaction},
+        utils::hex,
+    };
+
+    use super::*;
+    use crate::{
+        abi::{AbiSource, BuiltinSource},
+        contracts::Router,
+        decoder::DecodedCall,
+    };
+
+    const ROUTER_02: &str = "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D";
+    const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const DAI: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
+
+    // Raw calldata, selector first, encoded by hand rather than with the ABI under test
+    const SWAP_EXACT_ETH_FOR_TOKENS: &str = concat!(
+        "7ff36ab5",
+        "00000000000000000000000000000000000000000000000000000000713fb300",
+        "0000000000000000000000000000000000000000000000000000000000000080",
+        "000000000000000000000000abababababababababababababababababababab",
+        "000000000000000000000000000000000000000000000000000000006553f100",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+        "000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+        "000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+    );
+    const SWAP_ETH_FOR_EXACT_TOKENS: &str = concat!(
+        "fb3bdb41",
+        "0000000000000000000000000000000000000000000000000000000077359400",
+        "0000000000000000000000000000000000000000000000000000000000000080",
+        "000000000000000000000000abababababababababababababababababababab",
+        "000000000000000000000000000000000000000000000000000000006553f100",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+        "000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+        "000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+    );
+    const SWAP_TOKENS_FOR_EXACT_TOKENS: &str = concat!(
+        "8803dbee",
+        "00000000000000000000000000000000000000000000003635c9adc5dea00000",
+        "000000000000000000000000000000000000000000000000000000003e95ba80",
+        "00000000000000000000000000000000000000000000000000000000000000a0",
+        "000000000000000000000000abababababababababababababababababababab",
+        "000000000000000000000000000000000000000000000000000000006553f100",
+        "0000000000000000000000000000000000000000000000000000000000000003",
+        "000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        "000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+        "0000000000000000000000006b175474e89094c44da98b954eedeac495271d0f",
+    );
+    const SWAP_EXACT_TOKENS_FOR_ETH_SUPPORTING_FEE_ON_TRANSFER_TOKENS: &str = concat!(
+        "791ac947",
+        "00000000000000000000000000000000000000000000001b1ae4d6e2ef500000",
+        "000000000000000000000000000000000000000000000000016345785d8a0000",
+        "00000000000000000000000000000000000000000000000000000000000000a0",
+        "000000000000000000000000abababababababababababababababababababab",
+        "000000000000000000000000000000000000000000000000000000006553f100",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+        "0000000000000000000000006b175474e89094c44da98b954eedeac495271d0f",
+        "000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+    );
+    const ADD_LIQUIDITY_ETH: &str = concat!(
+        "f305d719",
+        "000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        "0000000000000000000000000000000000000000000000000000000077359400",
+        "00000000000000000000000000000000000000000000000000000000769cfd80",
+        "0000000000000000000000000000000000000000000000000dcef33a6f838000",
+        "000000000000000000000000abababababababababababababababababababab",
+        "000000000000000000000000000000000000000000000000000000006553f100",
+    );
+    const REMOVE_LIQUIDITY: &str = concat!(
+        "baa2abde",
+        "000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        "0000000000000000000000006b175474e89094c44da98b954eedeac495271d0f",
+        "000000000000000000000000000000000000000000000000000000e8d4a51000",
+        "000000000000000000000000000000000000000000000000000000003b9aca00",
+        "00000000000000000000000000000000000000000000003635c9adc5dea00000",
+        "000000000000000000000000abababababababababababababababababababab",
+        "000000000000000000000000000000000000000000000000000000006553f100",
+    );
+    fn address(address: &str) -> Address {
+        address.parse().unwrap()
+    }
+
+    fn recipient() -> Address {
+        Address::repeat_byte(0xab)
+    }
+
+    /// Decodes `calldata` sent to `UniswapV2Router02` along with `value`
+    async fn decode(calldata: &str, value: U256) -> Result<Option<DecodedCall>> {
+        let abi = BuiltinSource
+            .abi(Chain::Mainnet, address(ROUTER_02))
+            .await?
+            .context("no builtin router ABI")?;
+        let router = Router {
+            address: address(ROUTER_02),
+            abi,
+            name: "Uniswap V2: Router 2".to_string(),
+            version: 2,
+            factory: vec![],
+            wrapped_native: Some(address(WETH)),
+        };
+        let tx = Transaction {
+            to: Some(router.address),
+            value,
+            input: hex::decode(calldata)?.into(),
+            ..Default::default()
+        };
+        crate::decoder::decode(&router, &tx)
+    }
+
+    async fn swap(calldata: &str, value: U256) -> SwapIntent {
+        let call = decode(calldata, value).await.unwrap().unwrap();
+        match &call.intents[..] {
+            [Intent::Swap(swap)] => swap.clone(),
+            intents => panic!("expected a single swap, got {:?}", intents),
+        }
+    }
+
+    async fn liquidity(calldata: &str, value: U256) -> LiquidityIntent {
+        let call = decode(calldata, value).await.unwrap().unwrap();
+        match &call.intents[..] {
+            [Intent::Liquidity(liquidity)] => liquidity.clone(),
+            intents => panic!("expected a single liquidity change, got {:?}", intents),
+        }
+    }
+
+    #[tokio::test]
+    async fn decodes_eth_for_tokens() {
+        let value = U256::exp10(18);
+        let swap = swap(SWAP_EXACT_ETH_FOR_TOKENS, value).await;
+        assert_eq!(swap.function, "swapExactETHForTokens");
+        assert_eq!(swap.kind, SwapKind::ExactIn);
+        assert_eq!(swap.direction, SwapDirection::EthToToken);
+        assert!(swap.native_eth);
+        assert_eq!(swap.path, vec![address(WETH), address(USDC)]);
+        assert_eq!(swap.amount_in, Some(value));
+        assert_eq!(swap.amount_out, U256::from(1_900_000_000u64));
+        assert_eq!(swap.recipient, Some(recipient()));
+        assert_eq!(swap.deadline, Some(1_700_000_000.into()));
+        assert!(!swap.fee_on_transfer);
+        assert_eq!(swap.version, 2);
+    }
+
+    #[tokio::test]
+    async fn decodes_eth_for_exact_tokens() {
+        let value = U256::exp10(18);
+        let swap = swap(SWAP_ETH_FOR_EXACT_TOKENS, value).await;
+        assert_eq!(swap.kind, SwapKind::ExactOut);
+        assert_eq!(swap.direction, SwapDirection::EthToToken);
+        // The ETH sent is the most the swap spends
+        assert_eq!(swap.amount_in, Some(value));
+        assert_eq!(swap.amount_out, U256::from(2_000_000_000u64));
+    }
+
+    #[tokio::test]
+    async fn decodes_tokens_for_exact_tokens() {
+        let swap = swap(SWAP_TOKENS_FOR_EXACT_TOKENS, U256::zero()).await;
+        assert_eq!(swap.function, "swapTokensForExactTokens");
+        assert_eq!(swap.kind, SwapKind::ExactOut);
+        assert_eq!(swap.direction, SwapDirection::TokenToToken);
+        assert!(!swap.native_eth);
+        assert_eq!(swap.path, vec![address(USDC), address(WETH), address(DAI)]);
+        assert_eq!(swap.amount_in, Some(U256::from(1_050_000_000u64)));
+        assert_eq!(swap.amount_out, U256::exp10(21));
+    }
+
+    #[tokio::test]
+    async fn flags_fee_on_transfer_swaps() {
+        let swap = swap(
+            SWAP_EXACT_TOKENS_FOR_ETH_SUPPORTING_FEE_ON_TRANSFER_TOKENS,
+            U256::zero(),
+        )
+        .await;
+        assert_eq!(swap.kind, SwapKind::ExactIn);
+        assert_eq!(swap.direction, SwapDirection::TokenToEth);
+        assert!(swap.native_eth);
+        assert!(swap.fee_on_transfer);
+        assert_eq!(swap.path, vec![address(DAI), address(WETH)]);
+        assert_eq!(swap.amount_in, Some(U256::exp10(20) * 5));
+        assert_eq!(swap.amount_out, U256::exp10(17));
+    }
+
+    #[tokio::test]
+    async fn decodes_adding_eth_liquidity() {
+        let value = U256::exp10(18);
+        let liquidity = liquidity(ADD_LIQUIDITY_ETH, value).await;
+        assert_eq!(liquidity.action, LiquidityAction::Add);
+        assert!(liquidity.native_eth);
+        assert_eq!(liquidity.token_a, address(USDC));
+        assert_eq!(liquidity.token_b, None);
+        assert_eq!(
+            liquidity.amount_a_desired,
+            Some(U256::from(2_000_000_000u64))
+        );
+        assert_eq!(liquidity.amount_b_desired, Some(value));
+        assert_eq!(liquidity.amount_a_min, U256::from(1_990_000_000u64));
+        assert_eq!(liquidity.amount_b_min, U256::exp10(15) * 995);
+        assert_eq!(liquidity.liquidity, None);
+    }
+
+    #[tokio::test]
+    async fn decodes_removing_liquidity() {
+        let liquidity = liquidity(REMOVE_LIQUIDITY, U256::zero()).await;
+        assert_eq!(liquidity.action, LiquidityAction::Remove);
+        assert!(!liquidity.native_eth);
+        assert_eq!(liquidity.token_a, address(USDC));
+        assert_eq!(liquidity.token_b, Some(address(DAI)));
+        assert_eq!(liquidity.liquidity, Some(U256::exp10(12)));
+        assert_eq!(liquidity.amount_a_min, U256::exp10(9));
+        assert_eq!(liquidity.amount_b_min, U256::exp10(21));
+        assert_eq!(liquidity.recipient, Some(recipient()));
+    }
+
+    #[tokio::test]
+    async fn ignores_other_calls() {
+        // factory()
+        assert!(decode("c45a0155", U256::zero()).await.unwrap().is_none());
+        // Unknown selector
+        assert!(decode("deadbeef", U256::zero()).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,417 @@
+use anyhow::{bail, Context, Result};
+use ethers::{
+    abi::{Function, Token},
+    types::{Address, U256},
+};
+
+use super::{address, bytes, find_function, uint, v2};
+use crate::{
+    contracts::Router,
+    model::{Intent, SwapDirection, SwapIntent, SwapKind},
+};
+
+const ADDRESS_SIZE: usize = 20;
+const FEE_SIZE: usize = 3;
+
+/// Decodes a call to a Uniswap V3 `SwapRouter` or `SwapRouter02`
+///
+/// `multicall` payloads are unwrapped recursively against the same ABI, and
+/// the V2 swaps `SwapRouter02` forwards to its pairs are decoded as such
+pub fn decode(
+    router: &Router,
+    function: &Function,
+    input: &[u8],
+    value: U256,
+) -> Result<Vec<Intent>> {
+    let name = function.name.as_str();
+    match name {
+        "exactInputSingle" | "exactInput" | "exactOutputSingle" | "exactOutput" => {
+            let params = function
+                .decode_input(&input[4..])?
+                .into_iter()
+                .next()
+                .and_then(Token::into_tuple)
+                .with_context(|| format!("expected {} parameter struct", name))?;
+
+            let mut swap = decode_exact(name, &params)?;
+            pay_with_eth(router, &mut swap, value);
+            Ok(vec![Intent::Swap(swap)])
+        }
+        "multicall" => decode_multicall(router, function, input, value),
+        _ if name.starts_with("swap") => {
+            let mut intents: Vec<_> = v2::decode(function, input, value)?.into_iter().collect();
+            for intent in &mut intents {
+                if let Intent::Swap(swap) = intent {
+                    pay_with_eth(router, swap, value);
+                }
+            }
+            Ok(intents)
+        }
+        _ => Ok(vec![]),
+    }
+}
+
+/// ETH sent along is wrapped by the router to pay for swaps selling the
+/// wrapped native token
+fn pay_with_eth(router: &Router, swap: &mut SwapIntent, value: U256) {
+    let sells_wrapped_native = router
+        .wrapped_native
+        .is_none_or(|wrapped_native| swap.path.first() == Some(&wrapped_native));
+    if !value.is_zero() && sells_wrapped_native {
+        set_direction(swap, SwapDirection::EthToToken);
+    }
+}
+
+/// Splits a packed V3 path (`token, fee, token, ...`) into its tokens and the fee tier of each hop
+pub fn decode_path(path: &[u8]) -> Result<(Vec<Address>, Vec<u32>)> {
+    if path.len() < ADDRESS_SIZE
+        || !(path.len() - ADDRESS_SIZE).is_multiple_of(ADDRESS_SIZE + FEE_SIZE)
+    {
+        bail!("Malformed V3 path of {} bytes", path.len());
+    }
+
+    let mut tokens = vec![Address::from_slice(&path[..ADDRESS_SIZE])];
+    let mut fees = vec![];
+    for hop in path[ADDRESS_SIZE..].chunks(ADDRESS_SIZE + FEE_SIZE) {
+        fees.push(u32::from_be_bytes([0, hop[0], hop[1], hop[2]]));
+        tokens.push(Address::from_slice(&hop[FEE_SIZE..]));
+    }
+
+    Ok((tokens, fees))
+}
+
+/// Decodes every call bundled in `multicall(bytes[])`, `multicall(uint256,bytes[])`
+/// or `multicall(bytes32,bytes[])`
+fn decode_multicall(
+    router: &Router,
+    function: &Function,
+    input: &[u8],
+    value: U256,
+) -> Result<Vec<Intent>> {
+    let calls = function
+        .decode_input(&input[4..])?
+        .pop()
+        .and_then(Token::into_array)
+        .context("expected multicall payload")?;
+
+    let mut intents = vec![];
+    let mut unwraps_eth = false;
+    for call in calls.into_iter().filter_map(Token::into_bytes) {
+        let Some(inner) = find_function(&router.abi, &call) else {
+            continue;
+        };
+        unwraps_eth |= inner.name.starts_with("unwrapWETH9");
+        intents.extend(decode(router, inner, &call, value)?);
+    }
+
+    // The native leg of a multicall is only visible from the sibling calls
+    if unwraps_eth {
+        for intent in &mut intents {
+            if let Intent::Swap(swap) = intent {
+                set_direction(swap, SwapDirection::TokenToEth);
+            }
+        }
+    }
+
+    Ok(intents)
+}
+
+/// Decodes the parameter struct of the `exact*` functions
+///
+/// `SwapRouter02` dropped the `deadline` field the original `SwapRouter` structs
+/// carry, so the field positions are resolved from the struct length
+fn decode_exact(name: &str, params: &[Token]) -> Result<SwapIntent> {
+    let single = name.ends_with("Single");
+    let kind = if name.starts_with("exactOutput") {
+        SwapKind::ExactOut
+    } else {
+        SwapKind::ExactIn
+    };
+
+    let has_deadline = match (single, params.len()) {
+        (true, 8) | (false, 5) => true,
+        (true, 7) | (false, 4) => false,
+        _ => bail!("Unexpected {} struct of {} fields", name, params.len()),
+    };
+
+    let (path, fees) = if single {
+        let fee = uint(params, 2)?;
+        let fee = u32::try_from(fee)
+            .ok()
+            .with_context(|| format!("fee tier {} out of range", fee))?;
+        (vec![address(params, 0)?, address(params, 1)?], vec![fee])
+    } else {
+        let (mut path, mut fees) = decode_path(&bytes(params, 0)?)?;
+        // exactOutput paths are encoded from the output token backwards
+        if kind == SwapKind::ExactOut {
+            path.reverse();
+            fees.reverse();
+        }
+        (path, fees)
+    };
+
+    let recipient = if single { 3 } else { 1 };
+    let amount = recipient + 1 + usize::from(has_deadline);
+    let (amount, limit) = (uint(params, amount)?, uint(params, amount + 1)?);
+    let (amount_in, amount_out) = match kind {
+        SwapKind::ExactIn => (amount, limit),
+        SwapKind::ExactOut => (limit, amount),
+    };
+
+    Ok(SwapIntent {
+        function: name.to_string(),
+        kind,
+        direction: SwapDirection::TokenToToken,
+        native_eth: false,
+        path,
+        amount_in: Some(amount_in),
+        amount_out,
+        recipient: Some(address(params, recipient)?),
+        deadline: if has_deadline {
+            Some(uint(params, recipient + 1)?)
+        } else {
+            None
+        },
+        fee_on_transfer: false,
+        version: 3,
+        fees,
+        pools: vec![],
+        reserves: vec![],
+        quote: None,
+    })
+}
+
+/// Marks a token-to-token swap as having a native ETH leg
+fn set_direction(swap: &mut SwapIntent, direction: SwapDirection) {
+    if swap.direction == SwapDirection::TokenToToken {
+        swap.direction = direction;
+        swap.native_eth = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{abi::Abi, addressbook::Chain};
+
+    use super::*;
+    use crate::abi::{AbiSource, BuiltinSource};
+
+    const ROUTER_02: &str = "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45";
+    const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const DAI: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
+
+    fn address(address: &str) -> Address {
+        address.parse().unwrap()
+    }
+
+    fn recipient() -> Address {
+        Address::repeat_byte(0xab)
+    }
+
+    /// Packs `tokens` and the fee tier between each of them
+    fn path(tokens: &[&str], fees: &[u32]) -> Vec<u8> {
+        let mut path = address(tokens[0]).as_bytes().to_vec();
+        for (token, fee) in tokens[1..].iter().zip(fees) {
+            path.extend_from_slice(&fee.to_be_bytes()[1..]);
+            path.extend_from_slice(address(token).as_bytes());
+        }
+        path
+    }
+
+    async fn router() -> Router {
+        Router {
+            address: address(ROUTER_02),
+            abi: BuiltinSource
+                .abi(Chain::Mainnet, address(ROUTER_02))
+                .await
+                .unwrap()
+                .unwrap(),
+            name: "Uniswap V3: Router 2".to_string(),
+            version: 3,
+            factory: vec![],
+            wrapped_native: Some(address(WETH)),
+        }
+    }
+
+    /// Calldata of the `SwapRouter02` function `signature`, outputs left out
+    fn call(abi: &Abi, signature: &str, args: &[Token]) -> Vec<u8> {
+        abi.functions()
+            .find(|function| function.signature().split(':').next() == Some(signature))
+            .unwrap()
+            .encode_input(args)
+            .unwrap()
+    }
+
+    fn exact_input_single(abi: &Abi, amount_in: U256) -> Vec<u8> {
+        let params = Token::Tuple(vec![
+            Token::Address(address(WETH)),
+            Token::Address(address(USDC)),
+            Token::Uint(500.into()),
+            Token::Address(recipient()),
+            Token::Uint(amount_in),
+            Token::Uint(U256::exp10(9)),
+            Token::Uint(U256::zero()),
+        ]);
+        call(
+            abi,
+            "exactInputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+            &[params],
+        )
+    }
+
+    fn swap_exact_tokens_for_tokens(abi: &Abi, path: &[&str]) -> Vec<u8> {
+        call(
+            abi,
+            "swapExactTokensForTokens(uint256,uint256,address[],address)",
+            &[
+                Token::Uint(U256::exp10(18)),
+                Token::Uint(U256::exp10(9)),
+                Token::Array(
+                    path.iter()
+                        .map(|token| Token::Address(address(token)))
+                        .collect(),
+                ),
+                Token::Address(recipient()),
+            ],
+        )
+    }
+
+    async fn decode_call(input: &[u8], value: U256) -> Vec<SwapIntent> {
+        let router = router().await;
+        let function = find_function(&router.abi, input).unwrap();
+        decode(&router, function, input, value)
+            .unwrap()
+            .into_iter()
+            .filter_map(|intent| match intent {
+                Intent::Swap(swap) => Some(swap),
+                Intent::Liquidity(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_paths() {
+        let (tokens, fees) = decode_path(&path(&[USDC, WETH, DAI], &[500, 3000])).unwrap();
+        assert_eq!(tokens, vec![address(USDC), address(WETH), address(DAI)]);
+        assert_eq!(fees, vec![500, 3000]);
+
+        let (tokens, fees) = decode_path(&path(&[USDC], &[])).unwrap();
+        assert_eq!(tokens, vec![address(USDC)]);
+        assert!(fees.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        let path = path(&[USDC, WETH], &[500]);
+        for length in [0, 19, 21, 42, 44] {
+            let mut path = path.clone();
+            path.resize(length, 0);
+            assert!(decode_path(&path).is_err(), "{} bytes", length);
+        }
+    }
+
+    #[tokio::test]
+    async fn reverses_exact_output_paths() {
+        let abi = router().await.abi;
+        // Buys DAI with WETH through USDC, the path packed from DAI backwards
+        let params = Token::Tuple(vec![
+            Token::Bytes(path(&[DAI, USDC, WETH], &[100, 500])),
+            Token::Address(recipient()),
+            Token::Uint(U256::exp10(21)),
+            Token::Uint(U256::exp10(18)),
+        ]);
+        let input = call(
+            &abi,
+            "exactOutput((bytes,address,uint256,uint256))",
+            &[params],
+        );
+
+        let swaps = decode_call(&input, U256::zero()).await;
+        assert_eq!(swaps.len(), 1);
+        let swap = &swaps[0];
+        assert_eq!(swap.kind, SwapKind::ExactOut);
+        assert_eq!(swap.path, vec![address(WETH), address(USDC), address(DAI)]);
+        assert_eq!(swap.fees, vec![500, 100]);
+        assert_eq!(swap.amount_in, Some(U256::exp10(18)));
+        assert_eq!(swap.amount_out, U256::exp10(21));
+        assert_eq!(swap.recipient, Some(recipient()));
+        assert_eq!(swap.deadline, None);
+    }
+
+    #[tokio::test]
+    async fn unwraps_nested_multicalls() {
+        let abi = router().await.abi;
+        let inner = call(
+            &abi,
+            "multicall(bytes[])",
+            &[Token::Array(vec![Token::Bytes(exact_input_single(
+                &abi,
+                U256::exp10(18),
+            ))])],
+        );
+        let input = call(
+            &abi,
+            "multicall(uint256,bytes[])",
+            &[
+                Token::Uint(1_700_000_000.into()),
+                Token::Array(vec![
+                    Token::Bytes(inner),
+                    Token::Bytes(swap_exact_tokens_for_tokens(&abi, &[USDC, DAI])),
+                ]),
+            ],
+        );
+
+        let swaps = decode_call(&input, U256::zero()).await;
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps[0].function, "exactInputSingle");
+        assert_eq!(swaps[0].version, 3);
+        assert_eq!(swaps[0].path, vec![address(WETH), address(USDC)]);
+        assert_eq!(swaps[0].fees, vec![500]);
+        assert_eq!(swaps[1].function, "swapExactTokensForTokens");
+        assert_eq!(swaps[1].version, 2);
+        assert_eq!(swaps[1].path, vec![address(USDC), address(DAI)]);
+        assert!(swaps
+            .iter()
+            .all(|swap| swap.direction == SwapDirection::TokenToToken));
+    }
+
+    #[tokio::test]
+    async fn marks_swaps_paid_with_eth() {
+        let abi = router().await.abi;
+        let value = U256::exp10(18);
+
+        let swaps = decode_call(&exact_input_single(&abi, value), value).await;
+        assert_eq!(swaps[0].direction, SwapDirection::EthToToken);
+
+        let swaps = decode_call(&swap_exact_tokens_for_tokens(&abi, &[WETH, USDC]), value).await;
+        assert_eq!(swaps[0].direction, SwapDirection::EthToToken);
+        assert!(swaps[0].native_eth);
+
+        // ETH sent along does not pay for swaps selling another token
+        let swaps = decode_call(&swap_exact_tokens_for_tokens(&abi, &[USDC, DAI]), value).await;
+        assert_eq!(swaps[0].direction, SwapDirection::TokenToToken);
+    }
+
+    #[tokio::test]
+    async fn marks_swaps_unwrapped_to_eth() {
+        let abi = router().await.abi;
+        let input = call(
+            &abi,
+            "multicall(bytes[])",
+            &[Token::Array(vec![
+                Token::Bytes(swap_exact_tokens_for_tokens(&abi, &[USDC, WETH])),
+                Token::Bytes(call(
+                    &abi,
+                    "unwrapWETH9(uint256,address)",
+                    &[Token::Uint(U256::exp10(17)), Token::Address(recipient())],
+                )),
+            ])],
+        );
+
+        let swaps = decode_call(&input, U256::zero()).await;
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].direction, SwapDirection::TokenToEth);
+        assert!(swaps[0].native_eth);
+    }
+}
@@ -0,0 +1,301 @@
+use ansi_term::{Colour, Style};
+use anyhow::Result;
+use clap::ValueEnum;
+use ethers::types::{Address, U256};
+use serde::Deserialize;
+
+use crate::model::{
+    chain_name, ArbitrageOpportunity, InclusionEvent, InclusionStatus, Intent, LiquidityAction,
+    RouterEvent, SandwichOpportunity, SwapKind, WatcherEvent,
+};
+
+/// How the stdout and file sinks write events
+///
+/// JSON formats carry amounts as decimal strings and addresses and hashes as
+/// lowercase hex, see `model`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// An indented JSON object per event
+    Json,
+    /// One JSON object per line, for `jq` and friends
+    #[default]
+    Jsonl,
+    /// Colored summary for humans
+    Pretty,
+    /// Fixed-width columns, one row per swap or liquidity change
+    Table,
+}
+
+impl Format {
+    /// Line written once before the first event, for formats with column titles
+    pub fn header(self) -> Option<String> {
+        (self == Format::Table)
+            .then(|| row("CHAIN", "HASH", "ROUTER", "ACTION", "IN", "OUT", "DETAIL"))
+    }
+
+    /// Renders `event` as one or more lines, without the trailing newline
+    pub fn render(self, event: &WatcherEvent) -> Result<String> {
+        Ok(match self {
+            Format::Json => serde_json::to_string_pretty(event)?,
+            Format::Jsonl => serde_json::to_string(event)?,
+            Format::Pretty => match event {
+                WatcherEvent::Router(event) => pretty_router(event),
+                WatcherEvent::Inclusion(event) => pretty_inclusion(event),
+                WatcherEvent::SandwichOpportunity(event) => pretty_sandwich(event),
+                WatcherEvent::ArbitrageOpportunity(event) => pretty_arbitrage(event),
+            },
+            Format::Table => match event {
+                WatcherEvent::Router(event) => table_router(event),
+                WatcherEvent::Inclusion(event) => table_inclusion(event),
+                WatcherEvent::SandwichOpportunity(event) => table_sandwich(event),
+                WatcherEvent::ArbitrageOpportunity(event) => table_arbitrage(event),
+            },
+        })
+    }
+}
+
+fn pretty_router(event: &RouterEvent) -> String {
+    let mut lines = vec![format!(
+        "{} {} {} {} from {:?}",
+        Colour::Cyan.bold().paint(chain_name(event.chain_id)),
+        Style::new()
+            .dimmed()
+            .paint(format!("{:?}", event.transaction.hash)),
+        Colour::Yellow.paint(&event.router.name),
+        Style::new().bold().paint(&event.function),
+        event.transaction.from,
+    )];
+
+    for intent in &event.intents {
+        let line = match intent {
+            Intent::Swap(swap) => {
+                let (token_in, token_out) = (
+                    swap.path.first().map(token).unwrap_or_default(),
+                    swap.path.last().map(token).unwrap_or_default(),
+                );
+                // Spending a balance held by the router leaves the input amount unknown
+                let amount_in = swap
+                    .amount_in
+                    .map(|amount| amount.to_string())
+                    .unwrap_or_else(|| "some".to_string());
+                let trade = match swap.kind {
+                    SwapKind::ExactIn => format!(
+                        "sell {} {} for at least {} {}",
+                        amount_in, token_in, swap.amount_out, token_out
+                    ),
+                    SwapKind::ExactOut => format!(
+                        "buy {} {} for at most {} {}",
+                        swap.amount_out, token_out, amount_in, token_in
+                    ),
+                };
+                let quote = swap
+                    .quote
+                    .as_ref()
+                    .map(|quote| {
+                        format!(
+                            ", quoted {} -> {} with {} bps impact and {} bps slippage",
+                            quote.amounts[0],
+                            quote.amounts[quote.amounts.len() - 1],
+                            quote.price_impact_bps,
+                            quote.slippage_bps
+                        )
+                    })
+                    .unwrap_or_default();
+                format!(
+                    "{} {} on v{}{}",
+                    Colour::Green.paint("swap"),
+                    trade,
+                    swap.version,
+                    quote
+                )
+            }
+            Intent::Liquidity(liquidity) => {
+                let action = match liquidity.action {
+                    LiquidityAction::Add => "add liquidity",
+                    LiquidityAction::Remove => "remove liquidity",
+                };
+                format!(
+                    "{} {}/{} min {}/{}{}",
+                    Colour::Blue.paint(action),
+                    token(&liquidity.token_a),
+                    liquidity
+                        .token_b
+                        .as_ref()
+                        .map(token)
+                        .unwrap_or_else(|| "ETH".to_string()),
+                    liquidity.amount_a_min,
+                    liquidity.amount_b_min,
+                    liquidity
+                        .liquidity
+                        .map(|liquidity| format!(", burning {} LP", liquidity))
+                        .unwrap_or_default()
+                )
+            }
+        };
+        lines.push(format!("  {}", line));
+    }
+    lines.join("\n")
+}
+
+fn pretty_inclusion(event: &InclusionEvent) -> String {
+    let status = match event.status {
+        InclusionStatus::Included => Colour::Green.paint("included in"),
+        InclusionStatus::Dropped => Colour::Red.paint("dropped at"),
+    };
+    format!(
+        "{} {} {} block {}",
+        Colour::Cyan.bold().paint(chain_name(event.chain_id)),
+        Style::new().dimmed().paint(format!("{:?}", event.hash)),
+        status,
+        event.block_number
+    )
+}
+
+fn pretty_sandwich(event: &SandwichOpportunity) -> String {
+    format!(
+        "{} {} {} on {}: front-run {} {} -> {} {}, victim gets {} (min {}), back-run returns {}\n  {} net after {} gas at base fee {}",
+        Colour::Cyan.bold().paint(chain_name(event.chain_id)),
+        Style::new().dimmed().paint(format!("{:?}", event.hash)),
+        Colour::Purple.paint("sandwich"),
+        token(&event.pair),
+        event.front_run_in,
+        token(&event.token_in),
+        event.front_run_out,
+        token(&event.token_out),
+        event.victim_amount_out,
+        event.victim_amount_out_min,
+        event.back_run_out,
+        Colour::Green.bold().paint(event.net_profit.to_string()),
+        event.gas_cost,
+        event.base_fee
+    )
+}
+
+fn pretty_arbitrage(event: &ArbitrageOpportunity) -> String {
+    format!(
+        "{} {} {} behind {}: {} -> {} along {}\n  {} before gas, through {}",
+        Colour::Cyan.bold().paint(chain_name(event.chain_id)),
+        Style::new().dimmed().paint(format!("{:?}", event.hash)),
+        Colour::Purple.paint("arbitrage"),
+        token(&event.trigger_pool),
+        event.amount_in,
+        event.amount_out,
+        event.path.iter().map(token).collect::<Vec<_>>().join(" > "),
+        Colour::Green.bold().paint(event.profit.to_string()),
+        event.pools.iter().map(token).collect::<Vec<_>>().join(", ")
+    )
+}
+
+fn table_router(event: &RouterEvent) -> String {
+    let chain = chain_name(event.chain_id);
+    let hash = format!("{:?}", event.transaction.hash);
+    let rows: Vec<_> = event
+        .intents
+        .iter()
+        .map(|intent| match intent {
+            Intent::Swap(swap) => row(
+                &chain,
+                &hash,
+                &event.router.name,
+                &swap.function,
+                &amount(swap.amount_in),
+                &swap.amount_out.to_string(),
+                &swap.path.iter().map(token).collect::<Vec<_>>().join(">"),
+            ),
+            Intent::Liquidity(liquidity) => row(
+                &chain,
+                &hash,
+                &event.router.name,
+                &liquidity.function,
+                &amount(liquidity.amount_a_desired.or(liquidity.liquidity)),
+                &amount(liquidity.amount_b_desired),
+                &format!(
+                    "{}/{}",
+                    token(&liquidity.token_a),
+                    liquidity
+                        .token_b
+                        .as_ref()
+                        .map(token)
+                        .unwrap_or_else(|| "ETH".to_string())
+                ),
+            ),
+        })
+        .collect();
+
+    if rows.is_empty() {
+        return row(
+            &chain,
+            &hash,
+            &event.router.name,
+            &event.function,
+            "",
+            "",
+            "",
+        );
+    }
+    rows.join("\n")
+}
+
+fn table_inclusion(event: &InclusionEvent) -> String {
+    row(
+        &chain_name(event.chain_id),
+        &format!("{:?}", event.hash),
+        "",
+        &format!("{:?}", event.status).to_lowercase(),
+        "",
+        "",
+        &format!("block {}", event.block_number),
+    )
+}
+
+fn table_sandwich(event: &SandwichOpportunity) -> String {
+    row(
+        &chain_name(event.chain_id),
+        &format!("{:?}", event.hash),
+        &event.router,
+        "sandwich",
+        &event.front_run_in.to_string(),
+        &event.back_run_out.to_string(),
+        &format!("net {} on {}", event.net_profit, token(&event.pair)),
+    )
+}
+
+fn table_arbitrage(event: &ArbitrageOpportunity) -> String {
+    row(
+        &chain_name(event.chain_id),
+        &format!("{:?}", event.hash),
+        &event.router,
+        "arbitrage",
+        &event.amount_in.to_string(),
+        &event.amount_out.to_string(),
+        &format!(
+            "gross {} along {}",
+            event.profit,
+            event.path.iter().map(token).collect::<Vec<_>>().join(">")
+        ),
+    )
+}
+
+fn row(
+    chain: &str,
+    hash: &str,
+    router: &str,
+    action: &str,
+    amount_in: &str,
+    amount_out: &str,
+    detail: &str,
+) -> String {
+    format!(
+        "{:<10} {:<66} {:<28} {:<40} {:>28} {:>28} {}",
+        chain, hash, router, action, amount_in, amount_out, detail
+    )
+}
+
+fn token(address: &Address) -> String {
+    format!("{:?}", address)
+}
+
+fn amount(value: Option<U256>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
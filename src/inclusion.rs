@@ -0,0 +1,69 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use ethers::{addressbook::Chain, types::TxHash};
+
+use crate::model::{InclusionEvent, InclusionStatus};
+
+/// Router transactions reported as pending, waiting to show up in a block
+#[derive(Debug)]
+pub struct Inclusions {
+    pending: Mutex<HashMap<TxHash, Instant>>,
+    /// Time after which a transaction not seen in any block is given up on
+    timeout: Duration,
+}
+
+impl Inclusions {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: Mutex::default(),
+            timeout,
+        }
+    }
+
+    pub fn track(&self, hash: TxHash) {
+        self.lock().entry(hash).or_insert_with(Instant::now);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Stops tracking the transactions of block `number` and those pending for
+    /// longer than the timeout, returning what became of each
+    pub fn settle(&self, chain: Chain, number: u64, hashes: &[TxHash]) -> Vec<InclusionEvent> {
+        let mut pending = self.lock();
+        let mut events = vec![];
+        for hash in hashes {
+            if pending.remove(hash).is_some() {
+                events.push(InclusionEvent {
+                    chain_id: chain.into(),
+                    hash: *hash,
+                    status: InclusionStatus::Included,
+                    block_number: number,
+                });
+            }
+        }
+
+        pending.retain(|hash, seen| {
+            let expired = seen.elapsed() > self.timeout;
+            if expired {
+                events.push(InclusionEvent {
+                    chain_id: chain.into(),
+                    hash: *hash,
+                    status: InclusionStatus::Dropped,
+                    block_number: number,
+                });
+            }
+            !expired
+        });
+        events
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<TxHash, Instant>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,359 @@
+use ethers::{
+    addressbook::Chain,
+    types::{Address, Transaction, H256, U256},
+};
+use serde::{Serialize, Serializer};
+
+use crate::{contracts::Router, decoder::DecodedCall};
+
+/// Name of a chain known to ethers, or its id otherwise
+pub fn chain_name(chain_id: u64) -> String {
+    Chain::try_from(chain_id)
+        .map(|chain| chain.to_string())
+        .unwrap_or_else(|_| chain_id.to_string())
+}
+
+/// Serializes amounts as decimal strings instead of hex quantities, so they
+/// survive tools that parse JSON numbers as doubles
+mod decimal {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            value: &Option<U256>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => serializer.collect_str(value),
+                None => serializer.serialize_none(),
+            }
+        }
+    }
+
+    pub mod seq {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(values: &[U256], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(values.iter().map(U256::to_string))
+        }
+    }
+}
+
+/// Which side of a swap the caller fixed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapKind {
+    ExactIn,
+    ExactOut,
+}
+
+/// Direction of a swap relative to the native currency
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapDirection {
+    EthToToken,
+    TokenToEth,
+    TokenToToken,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiquidityAction {
+    Add,
+    Remove,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SwapIntent {
+    /// Name of the router function, e.g. `swapExactETHForTokens`
+    pub function: String,
+    pub kind: SwapKind,
+    pub direction: SwapDirection,
+    /// Whether one leg of the swap is native ETH rather than a token
+    pub native_eth: bool,
+    /// Tokens the swap routes through, input first
+    pub path: Vec<Address>,
+    /// Exact input for exact-in swaps, maximum input for exact-out swaps.
+    /// `None` when the swap spends tokens the call holds or sent ahead in
+    /// amounts its calldata does not reveal
+    #[serde(serialize_with = "decimal::option::serialize")]
+    pub amount_in: Option<U256>,
+    /// Minimum output for exact-in swaps, exact output for exact-out swaps
+    #[serde(serialize_with = "decimal::serialize")]
+    pub amount_out: U256,
+    pub recipient: Option<Address>,
+    #[serde(serialize_with = "decimal::option::serialize")]
+    pub deadline: Option<U256>,
+    /// Whether the router tolerates fee-on-transfer tokens along the path
+    pub fee_on_transfer: bool,
+    /// Uniswap version of the pools the swap trades against
+    pub version: u8,
+    /// Fee tier of each V3 hop in hundredths of a bip, empty for V2 pairs
+    pub fees: Vec<u32>,
+    /// V2 pairs the swap trades against, one per hop, when all of them could be resolved
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pools: Vec<Address>,
+    /// Reserves of each pool as of the latest block seen, when all of them are tracked
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reserves: Vec<Reserves>,
+    /// Expected execution against `reserves`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quote: Option<SwapQuote>,
+}
+
+/// What a V2 swap will do if mined against the reserves it was quoted with
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SwapQuote {
+    /// Amounts along the path, input first, as returned by `getAmountsOut`
+    /// for exact-in swaps and `getAmountsIn` for exact-out swaps
+    #[serde(serialize_with = "decimal::seq::serialize")]
+    pub amounts: Vec<U256>,
+    /// Price impact of each hop in basis points, the 0.3% fee included
+    pub hop_impact_bps: Vec<u32>,
+    /// Price impact of the whole swap in basis points, the fees included
+    pub price_impact_bps: u32,
+    /// How far the output can fall (exact-in) or the input rise (exact-out) from the
+    /// quote before the swap reverts, in basis points of the quoted amount.
+    /// Negative when the swap would already revert
+    pub slippage_bps: i64,
+}
+
+/// Reserves of a V2 pair as of the end of a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Reserves {
+    #[serde(serialize_with = "decimal::serialize")]
+    pub reserve0: U256,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub reserve1: U256,
+    pub block_number: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LiquidityIntent {
+    /// Name of the router function, e.g. `addLiquidityETH`
+    pub function: String,
+    pub action: LiquidityAction,
+    /// Whether the pair's second leg is native ETH, leaving `token_b` unset
+    pub native_eth: bool,
+    pub token_a: Address,
+    pub token_b: Option<Address>,
+    /// Amounts offered to the pair when adding liquidity
+    #[serde(serialize_with = "decimal::option::serialize")]
+    pub amount_a_desired: Option<U256>,
+    #[serde(serialize_with = "decimal::option::serialize")]
+    pub amount_b_desired: Option<U256>,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub amount_a_min: U256,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub amount_b_min: U256,
+    /// LP tokens burned when removing liquidity
+    #[serde(serialize_with = "decimal::option::serialize")]
+    pub liquidity: Option<U256>,
+    pub recipient: Option<Address>,
+    #[serde(serialize_with = "decimal::option::serialize")]
+    pub deadline: Option<U256>,
+}
+
+/// What a pending router call will do once mined
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Intent {
+    Swap(SwapIntent),
+    Liquidity(LiquidityIntent),
+}
+
+/// Sender and gas parameters of a pending transaction
+#[derive(Clone, Debug, Serialize)]
+pub struct PendingTransaction {
+    pub hash: H256,
+    pub from: Address,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub nonce: U256,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub value: U256,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub gas: U256,
+    #[serde(serialize_with = "decimal::option::serialize")]
+    pub gas_price: Option<U256>,
+    #[serde(serialize_with = "decimal::option::serialize")]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(serialize_with = "decimal::option::serialize")]
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+impl From<&Transaction> for PendingTransaction {
+    fn from(tx: &Transaction) -> Self {
+        Self {
+            hash: tx.hash,
+            from: tx.from,
+            nonce: tx.nonce,
+            value: tx.value,
+            gas: tx.gas,
+            gas_price: tx.gas_price,
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+        }
+    }
+}
+
+/// The router a transaction was sent to, and the factories backing it
+#[derive(Clone, Debug, Serialize)]
+pub struct RouterRef {
+    pub address: Address,
+    pub name: String,
+    pub version: u8,
+    pub factories: Vec<Address>,
+}
+
+impl From<&Router> for RouterRef {
+    fn from(router: &Router) -> Self {
+        Self {
+            address: router.address,
+            name: router.name.clone(),
+            version: router.version,
+            factories: router
+                .factory
+                .iter()
+                .map(|factory| factory.address)
+                .collect(),
+        }
+    }
+}
+
+/// A decoded router call together with the transaction carrying it
+#[derive(Clone, Debug, Serialize)]
+pub struct RouterEvent {
+    pub chain_id: u64,
+    pub transaction: PendingTransaction,
+    pub router: RouterRef,
+    /// Name of the top-level router function
+    pub function: String,
+    pub intents: Vec<Intent>,
+}
+
+impl RouterEvent {
+    pub fn new(chain: Chain, tx: &Transaction, router: &Router, call: DecodedCall) -> Self {
+        Self {
+            chain_id: chain.into(),
+            transaction: tx.into(),
+            router: router.into(),
+            function: call.function,
+            intents: call.intents,
+        }
+    }
+}
+
+/// What became of a router transaction previously reported as pending
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InclusionStatus {
+    Included,
+    /// Not seen in any block before the inclusion timeout, usually replaced or evicted
+    Dropped,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InclusionEvent {
+    pub chain_id: u64,
+    pub hash: H256,
+    pub status: InclusionStatus,
+    /// Block the transaction was included in, or the block at which it was given up on
+    pub block_number: u64,
+}
+
+/// A pending V2 swap that can be sandwiched at a profit: buy ahead of it on its
+/// first pair, let it trade at the worse price, then sell back
+///
+/// Amounts of `token_in` are in the wrapped native token
+#[derive(Clone, Debug, Serialize)]
+pub struct SandwichOpportunity {
+    pub chain_id: u64,
+    /// The victim transaction
+    pub hash: H256,
+    pub router: String,
+    /// Pair both legs trade against, the first hop of the victim
+    pub pair: Address,
+    pub token_in: Address,
+    pub token_out: Address,
+    /// Spent by the front-run
+    #[serde(serialize_with = "decimal::serialize")]
+    pub front_run_in: U256,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub front_run_out: U256,
+    /// Output of the victim behind the front-run, at least its minimum
+    #[serde(serialize_with = "decimal::serialize")]
+    pub victim_amount_out: U256,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub victim_amount_out_min: U256,
+    /// Received by the back-run, selling the whole front-run output
+    #[serde(serialize_with = "decimal::serialize")]
+    pub back_run_out: U256,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub gross_profit: U256,
+    /// Gas of both legs at `base_fee` plus the priority fee
+    #[serde(serialize_with = "decimal::serialize")]
+    pub gas_cost: U256,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub net_profit: U256,
+    /// Base fee of the next block
+    #[serde(serialize_with = "decimal::serialize")]
+    pub base_fee: U256,
+    /// Block the reserves are as of
+    pub block_number: u64,
+}
+
+/// A round trip through pairs of the registered factories that a pending swap
+/// makes profitable by moving the price of one of them
+///
+/// Round trips start and end with the wrapped native token, in which amounts are
+/// counted
+#[derive(Clone, Debug, Serialize)]
+pub struct ArbitrageOpportunity {
+    pub chain_id: u64,
+    /// The pending swap moving the price
+    pub hash: H256,
+    pub router: String,
+    /// Pair of the pending swap the round trip goes through
+    pub trigger_pool: Address,
+    /// Tokens of the round trip, the first one repeated at the end
+    pub path: Vec<Address>,
+    /// Pair of each hop
+    pub pools: Vec<Address>,
+    /// Input maximizing the profit
+    #[serde(serialize_with = "decimal::serialize")]
+    pub amount_in: U256,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub amount_out: U256,
+    /// Before gas
+    #[serde(serialize_with = "decimal::serialize")]
+    pub profit: U256,
+    /// Oldest block the reserves used are as of
+    pub block_number: u64,
+}
+
+/// Everything the watcher reports to its sinks
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WatcherEvent {
+    Router(Box<RouterEvent>),
+    Inclusion(InclusionEvent),
+    SandwichOpportunity(Box<SandwichOpportunity>),
+    ArbitrageOpportunity(Box<ArbitrageOpportunity>),
+}
+
+impl WatcherEvent {
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            WatcherEvent::Router(event) => event.chain_id,
+            WatcherEvent::Inclusion(event) => event.chain_id,
+            WatcherEvent::SandwichOpportunity(event) => event.chain_id,
+            WatcherEvent::ArbitrageOpportunity(event) => event.chain_id,
+        }
+    }
+}
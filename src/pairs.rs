@@ -0,0 +1,348 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use anyhow::{Context, Result};
+use ethers::{
+    abi::{decode, ParamType},
+    addressbook::Chain,
+    providers::{Middleware, Provider, Ws},
+    types::{Address, Filter, Log},
+};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    config::PairsConfig,
+    contracts::{sort_tokens, Factory, Router},
+    model::Intent,
+};
+
+/// Event emitted by Uniswap V2 style factories for every new pair
+const PAIR_CREATED: &str = "PairCreated(address,address,address,uint256)";
+
+/// A pair as created by its factory, tokens sorted by address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pair {
+    pub token0: Address,
+    pub token1: Address,
+    pub address: Address,
+}
+
+/// Pairs of a factory and how far its logs have been scanned
+#[derive(Debug, Default)]
+struct FactoryPairs {
+    /// Block the backfill starts at
+    from_block: u64,
+    /// Last block whose logs are reflected in `pairs`
+    synced_to: Option<u64>,
+    pairs: HashMap<(Address, Address), Address>,
+    /// Other token and pair of every pair trading each token
+    tokens: HashMap<Address, Vec<(Address, Address)>>,
+}
+
+impl FactoryPairs {
+    /// Adds a pair, returning whether it was new
+    fn insert(&mut self, token0: Address, token1: Address, pair: Address) -> bool {
+        if self.pairs.insert((token0, token1), pair).is_some() {
+            return false;
+        }
+        self.tokens.entry(token0).or_default().push((token1, pair));
+        self.tokens.entry(token1).or_default().push((token0, pair));
+        true
+    }
+}
+
+/// On-disk form of the registry, `{dir}/{chain id}.json`
+#[derive(Default, Serialize, Deserialize)]
+struct Stored {
+    factories: HashMap<Address, StoredFactory>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct StoredFactory {
+    synced_to: Option<u64>,
+    pairs: Vec<Pair>,
+}
+
+/// Pairs of a chain's V2 factories, built from their `PairCreated` logs
+///
+/// The registry is backfilled from each factory's `from_block` and then kept up
+/// to date as blocks are mined. Progress is saved to disk, so a restart only
+/// scans the blocks mined since
+#[derive(Debug)]
+pub struct PairRegistry {
+    chain: Chain,
+    file: PathBuf,
+    /// Blocks covered by a single `eth_getLogs` request
+    batch_size: u64,
+    factories: RwLock<HashMap<Address, FactoryPairs>>,
+    /// Held while scanning, so that overlapping syncs skip instead of queueing
+    syncing: Mutex<()>,
+}
+
+impl PairRegistry {
+    /// Loads the saved pairs of the V2 factories among `factories`
+    pub fn load(chain: Chain, factories: &[Factory], config: &PairsConfig) -> Result<Self> {
+        let file = config.dir.join(format!("{}.json", u64::from(chain)));
+        let mut stored = match std::fs::read(&file) {
+            Ok(contents) => serde_json::from_slice::<Stored>(&contents)
+                .with_context(|| format!("Could not parse {}", file.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Stored::default(),
+            Err(e) => return Err(e).with_context(|| format!("Could not read {}", file.display())),
+        };
+
+        let mut registry = HashMap::new();
+        for factory in factories.iter().filter(|factory| factory.version == 2) {
+            // Routers sharing a factory list it more than once
+            if registry.contains_key(&factory.address) {
+                continue;
+            }
+            let stored = stored
+                .factories
+                .remove(&factory.address)
+                .unwrap_or_default();
+            let mut pairs = FactoryPairs {
+                from_block: factory.from_block,
+                synced_to: stored.synced_to,
+                ..Default::default()
+            };
+            for pair in stored.pairs {
+                pairs.insert(pair.token0, pair.token1, pair.address);
+            }
+            registry.insert(factory.address, pairs);
+        }
+
+        let registry = Self {
+            chain,
+            file,
+            batch_size: config.batch_size,
+            factories: RwLock::new(registry),
+            syncing: Mutex::new(()),
+        };
+        info!("{}: loaded {} pairs", chain, registry.len());
+        Ok(registry)
+    }
+
+    fn len(&self) -> usize {
+        self.read()
+            .values()
+            .map(|factory| factory.pairs.len())
+            .sum()
+    }
+
+    /// Pair of `factory` trading `a` against `b`, in either order
+    pub fn pair(&self, factory: Address, a: Address, b: Address) -> Option<Address> {
+        self.read()
+            .get(&factory)?
+            .pairs
+            .get(&sort_tokens(a, b))
+            .copied()
+    }
+
+    /// Pairs of any factory trading `a` against `b`
+    pub fn pairs_between(&self, a: Address, b: Address) -> Vec<Address> {
+        let tokens = sort_tokens(a, b);
+        self.read()
+            .values()
+            .filter_map(|factory| factory.pairs.get(&tokens).copied())
+            .collect()
+    }
+
+    /// Other token and pair of every pair of any factory trading `token`
+    pub fn pairs_of(&self, token: Address) -> Vec<(Address, Address)> {
+        self.read()
+            .values()
+            .filter_map(|factory| factory.tokens.get(&token))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Scans the factory logs up to block `head`, backfilling on the first call,
+    /// and returns the pairs found. Returns immediately if a scan is already running
+    pub async fn sync(&self, provider: &Provider<Ws>, head: u64) -> Result<Vec<Address>> {
+        let Ok(_syncing) = self.syncing.try_lock() else {
+            return Ok(vec![]);
+        };
+
+        let factories: Vec<_> = self
+            .read()
+            .iter()
+            .map(|(address, factory)| {
+                let from = factory
+                    .synced_to
+                    .map_or(factory.from_block, |synced_to| synced_to + 1);
+                (*address, from)
+            })
+            .collect();
+
+        let mut found = vec![];
+        let mut backfilled = false;
+        let mut result = Ok(());
+        for (factory, mut from) in factories {
+            let backfill = head.saturating_sub(from) >= self.batch_size;
+            backfilled |= backfill;
+            while from <= head {
+                let to = head.min(from + self.batch_size - 1);
+                let filter = Filter::new()
+                    .address(factory)
+                    .event(PAIR_CREATED)
+                    .from_block(from)
+                    .to_block(to);
+                let logs = match provider.get_logs(&filter).await {
+                    Ok(logs) => logs,
+                    Err(e) => {
+                        result = Err(e).with_context(|| {
+                            format!("Could not fetch pairs of {:?} from block {}", factory, from)
+                        });
+                        break;
+                    }
+                };
+
+                let mut registry = self.write();
+                if let Some(entry) = registry.get_mut(&factory) {
+                    for pair in logs.iter().filter_map(parse) {
+                        if entry.insert(pair.token0, pair.token1, pair.address) {
+                            debug!("{}: new pair {:?}", self.chain, pair);
+                            found.push(pair.address);
+                        }
+                    }
+                    entry.synced_to = Some(to);
+                }
+                drop(registry);
+
+                if backfill {
+                    debug!(
+                        "{}: scanned pairs of {:?} up to block {}",
+                        self.chain, factory, to
+                    );
+                }
+                from = to + 1;
+            }
+            if result.is_err() {
+                break;
+            }
+        }
+
+        // Saving on every new head would rewrite the whole file for nothing
+        if !found.is_empty() || backfilled {
+            info!(
+                "{}: {} new pairs, {} in total",
+                self.chain,
+                found.len(),
+                self.len()
+            );
+            self.save()?;
+        }
+        result.map(|_| found)
+    }
+
+    fn save(&self) -> Result<()> {
+        let stored = Stored {
+            factories: self
+                .read()
+                .iter()
+                .map(|(address, factory)| {
+                    let pairs = factory
+                        .pairs
+                        .iter()
+                        .map(|(&(token0, token1), &address)| Pair {
+                            token0,
+                            token1,
+                            address,
+                        })
+                        .collect();
+                    (
+                        *address,
+                        StoredFactory {
+                            synced_to: factory.synced_to,
+                            pairs,
+                        },
+                    )
+                })
+                .collect(),
+        };
+
+        if let Some(dir) = self.file.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create {}", dir.display()))?;
+        }
+        // Written aside and renamed, so an interrupted save keeps the previous file
+        let partial = self.file.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_vec(&stored)?)
+            .with_context(|| format!("Could not write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.file)
+            .with_context(|| format!("Could not write {}", self.file.display()))?;
+        Ok(())
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<Address, FactoryPairs>> {
+        self.factories.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<Address, FactoryPairs>> {
+        self.factories.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Fills in the pairs each V2 swap among `intents` trades against
+///
+/// Hops are looked up in `registry` first, then computed from the first of the
+/// router's factories with an init code hash, so no RPC call is needed either way
+pub fn resolve(registry: Option<&PairRegistry>, router: &Router, intents: &mut [Intent]) {
+    let factories: Vec<_> = router
+        .factory
+        .iter()
+        .filter(|factory| factory.version == 2)
+        .collect();
+    for intent in intents {
+        let Intent::Swap(swap) = intent else {
+            continue;
+        };
+        if swap.version != 2 {
+            continue;
+        }
+
+        let pools = match registry {
+            Some(registry) => swap
+                .path
+                .windows(2)
+                .map(|hop| {
+                    factories
+                        .iter()
+                        .find_map(|factory| registry.pair(factory.address, hop[0], hop[1]))
+                        .or_else(|| {
+                            factories
+                                .iter()
+                                .find_map(|factory| factory.pair_address(hop[0], hop[1]))
+                        })
+                })
+                .collect(),
+            None => factories
+                .iter()
+                .find_map(|factory| factory.path_pairs(&swap.path)),
+        };
+        swap.pools = pools.unwrap_or_default();
+    }
+}
+
+/// Reads a `PairCreated` log, with both tokens indexed and the pair in the data
+fn parse(log: &Log) -> Option<Pair> {
+    let [_, token0, token1] = log.topics.as_slice() else {
+        return None;
+    };
+    let pair = decode(&[ParamType::Address, ParamType::Uint(256)], &log.data)
+        .ok()?
+        .into_iter()
+        .next()?
+        .into_address()?;
+    Some(Pair {
+        token0: Address::from(*token0),
+        token1: Address::from(*token1),
+        address: pair,
+    })
+}
@@ -0,0 +1,520 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use ethers::{
+    addressbook::Chain,
+    providers::{Middleware, Provider, Ws},
+    types::{Transaction, TxHash, U256, U64},
+};
+use log::{debug, warn};
+use serde::Deserialize;
+use tokio::{
+    sync::{mpsc, Notify, Semaphore},
+    task::{JoinHandle, JoinSet},
+};
+
+use crate::{
+    amm,
+    analysis::{arbitrage::ArbitrageDetector, sandwich::SandwichDetector},
+    backoff::Backoff,
+    config::{OverflowPolicy, WatcherConfig},
+    contracts::Router,
+    inclusion::Inclusions,
+    model::{Intent, RouterEvent, WatcherEvent},
+    pairs::{self, PairRegistry},
+    reserves::ReserveTracker,
+    rpc::{classify, ErrorClass},
+    sink::Sinks,
+};
+
+/// Delay before the first retry of a transiently failed fetch
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Item of a `newPendingTransactions` subscription, depending on whether
+/// the node was asked for full transaction bodies
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Pending {
+    Hash(TxHash),
+    Transaction(Box<Transaction>),
+}
+
+/// Counters shared by the pipeline stages, kept across reconnects
+#[derive(Debug, Default)]
+pub struct Metrics {
+    received: AtomicU64,
+    dropped: AtomicU64,
+    /// Microseconds the ingest stage spent waiting for queue space
+    blocked: AtomicU64,
+    fetched: AtomicU64,
+    /// Fetch attempts repeated after a transient error
+    retried: AtomicU64,
+    missing: AtomicU64,
+    failed: AtomicU64,
+    decoded: AtomicU64,
+}
+
+impl Metrics {
+    fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
+
+/// Bounded FIFO between the ingest and fetch stages
+///
+/// When full, `push` either waits for space or evicts the oldest entry,
+/// depending on the overflow policy. Meant for a single producer and a single consumer
+struct Queue<T> {
+    items: Mutex<VecDeque<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    closed: AtomicBool,
+    pushed: Notify,
+    popped: Notify,
+}
+
+impl<T> Queue<T> {
+    fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            policy,
+            closed: AtomicBool::new(false),
+            pushed: Notify::new(),
+            popped: Notify::new(),
+        }
+    }
+
+    /// Appends `item`, returning the entry evicted to make room for it, if any
+    async fn push(&self, item: T) -> Option<T> {
+        loop {
+            {
+                let mut items = self.items.lock().unwrap_or_else(|e| e.into_inner());
+                if items.len() < self.capacity {
+                    items.push_back(item);
+                    self.pushed.notify_one();
+                    return None;
+                }
+                if self.policy == OverflowPolicy::DropOldest {
+                    let oldest = items.pop_front();
+                    items.push_back(item);
+                    self.pushed.notify_one();
+                    return oldest;
+                }
+            }
+            self.popped.notified().await;
+        }
+    }
+
+    /// Returns the oldest entry, or `None` once the queue is closed and empty
+    async fn pop(&self) -> Option<T> {
+        loop {
+            {
+                let mut items = self.items.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(item) = items.pop_front() {
+                    self.popped.notify_one();
+                    return Some(item);
+                }
+                if self.closed.load(Ordering::Acquire) {
+                    return None;
+                }
+            }
+            self.pushed.notified().await;
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.pushed.notify_one();
+    }
+
+    fn len(&self) -> usize {
+        self.items.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+/// First fatal error raised by a fetch, ending the watcher
+#[derive(Default)]
+struct Fatal {
+    error: Mutex<Option<anyhow::Error>>,
+    raised: Notify,
+}
+
+impl Fatal {
+    fn raise(&self, error: anyhow::Error) {
+        let mut slot = self.error.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_none() {
+            *slot = Some(error);
+            self.raised.notify_one();
+        }
+    }
+
+    async fn wait(&self) -> anyhow::Error {
+        loop {
+            if let Some(error) = self.error.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                return error;
+            }
+            self.raised.notified().await;
+        }
+    }
+}
+
+/// State of a chain's watcher that outlives the pipeline of a single subscription
+#[derive(Clone)]
+pub struct Context {
+    pub metrics: Arc<Metrics>,
+    pub inclusions: Arc<Inclusions>,
+    pub pairs: Option<Arc<PairRegistry>>,
+    pub reserves: Option<Arc<ReserveTracker>>,
+    pub sandwiches: Option<Arc<SandwichDetector>>,
+    pub arbitrage: Option<Arc<ArbitrageDetector>>,
+    pub sinks: Arc<Sinks>,
+}
+
+/// State shared by the pipeline and its fetch tasks
+struct Shared {
+    chain: Chain,
+    provider: Provider<Ws>,
+    routers: Arc<Vec<Router>>,
+    transactions: mpsc::Sender<Transaction>,
+    /// Attempts made after a transient error before a hash is given up on
+    retries: u32,
+    fatal: Fatal,
+    context: Context,
+}
+
+impl Shared {
+    /// Fetches `hash` and forwards it to the decoder if it calls a router,
+    /// retrying transient errors with backoff
+    async fn fetch(&self, hash: TxHash) {
+        let mut backoff = Backoff::new(RETRY_DELAY, MAX_RETRY_DELAY);
+        loop {
+            let error = match self.provider.get_transaction(hash).await {
+                Ok(Some(tx)) => {
+                    Metrics::add(&self.context.metrics.fetched, 1);
+                    if is_routed(&self.routers, &tx) {
+                        self.transactions.send(tx).await.ok();
+                    }
+                    return;
+                }
+                Ok(None) => {
+                    Metrics::add(&self.context.metrics.missing, 1);
+                    return;
+                }
+                Err(e) => e,
+            };
+
+            match classify(&error) {
+                ErrorClass::Transient if backoff.attempt() < self.retries => {
+                    Metrics::add(&self.context.metrics.retried, 1);
+                    let delay = backoff.next_delay();
+                    debug!("Retrying {:?} in {:?}: {}", hash, delay, error);
+                    tokio::time::sleep(delay).await;
+                }
+                ErrorClass::Transient | ErrorClass::Rejected => {
+                    Metrics::add(&self.context.metrics.failed, 1);
+                    warn!("Could not fetch {:?}: {}", hash, error);
+                    return;
+                }
+                ErrorClass::NotFound => {
+                    Metrics::add(&self.context.metrics.missing, 1);
+                    debug!("{:?} is no longer pending: {}", hash, error);
+                    return;
+                }
+                ErrorClass::Fatal => {
+                    self.fatal.raise(
+                        anyhow::Error::new(error).context(format!("Could not fetch {:?}", hash)),
+                    );
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Reports what became of tracked router transactions once block `number` is mined
+    async fn settle(&self, number: U64) {
+        let inclusions = &self.context.inclusions;
+        if inclusions.is_empty() {
+            return;
+        }
+        let hashes = match self.provider.get_block(number).await {
+            Ok(block) => block.map(|block| block.transactions).unwrap_or_default(),
+            Err(e) => {
+                warn!("Could not fetch block {}: {}", number, e);
+                return;
+            }
+        };
+
+        for event in inclusions.settle(self.chain, number.as_u64(), &hashes) {
+            debug!("{:?} {:?} at block {}", event.hash, event.status, number);
+            self.context.sinks.dispatch(WatcherEvent::Inclusion(event));
+        }
+    }
+}
+
+/// Ingest, fetch and decode stages of a single subscription
+///
+/// Hashes are queued by `submit`, fetched by up to `fetch_concurrency` concurrent
+/// requests, and router transactions are handed to a decoder over a bounded channel.
+/// Full transactions streamed by the node skip the queue and go straight to the decoder
+pub struct Pipeline {
+    chain: Chain,
+    queue: Arc<Queue<TxHash>>,
+    fetches: Arc<Semaphore>,
+    concurrency: usize,
+    shared: Arc<Shared>,
+    fetcher: JoinHandle<()>,
+}
+
+impl Pipeline {
+    pub fn start(
+        chain: Chain,
+        provider: Provider<Ws>,
+        routers: Arc<Vec<Router>>,
+        settings: &WatcherConfig,
+        context: Context,
+    ) -> Self {
+        let queue = Arc::new(Queue::new(settings.queue_capacity, settings.overflow));
+        let fetches = Arc::new(Semaphore::new(settings.fetch_concurrency));
+        let (sender, receiver) = mpsc::channel(settings.queue_capacity);
+
+        // The decoder drains whatever was received and stops once the pipeline is stopped
+        tokio::spawn(decode(
+            chain,
+            provider.clone(),
+            routers.clone(),
+            receiver,
+            context.clone(),
+        ));
+        let shared = Arc::new(Shared {
+            chain,
+            provider,
+            routers,
+            transactions: sender,
+            retries: settings.fetch_retries,
+            fatal: Fatal::default(),
+            context,
+        });
+        let fetcher = tokio::spawn(fetch(queue.clone(), fetches.clone(), shared.clone()));
+
+        Self {
+            chain,
+            queue,
+            fetches,
+            concurrency: settings.fetch_concurrency,
+            shared,
+            fetcher,
+        }
+    }
+
+    /// Queues a pending hash for fetching, or hands a full transaction to the decoder
+    pub async fn submit(&self, pending: Pending) {
+        let metrics = &self.shared.context.metrics;
+        Metrics::add(&metrics.received, 1);
+        let hash = match pending {
+            Pending::Hash(hash) => hash,
+            Pending::Transaction(tx) => {
+                if is_routed(&self.shared.routers, &tx) {
+                    self.shared.transactions.send(*tx).await.ok();
+                }
+                return;
+            }
+        };
+
+        let started = Instant::now();
+        if let Some(dropped) = self.queue.push(hash).await {
+            debug!("Queue full, dropping {:?}", dropped);
+            Metrics::add(&metrics.dropped, 1);
+        }
+        Metrics::add(&metrics.blocked, started.elapsed().as_micros() as u64);
+    }
+
+    /// Settles tracked transactions against newly mined block `number` and
+    /// picks up new pairs and reserve updates, in the background. `base_fee` is
+    /// that of the next block
+    pub fn mined(&self, number: U64, base_fee: Option<U256>) {
+        if let (Some(sandwiches), Some(base_fee)) = (&self.shared.context.sandwiches, base_fee) {
+            sandwiches.set_base_fee(base_fee);
+        }
+        let shared = self.shared.clone();
+        tokio::spawn(async move { shared.settle(number).await });
+
+        // Only the provider is moved, a long backfill must not keep the pipeline alive
+        let (chain, provider) = (self.chain, self.shared.provider.clone());
+        let Context {
+            pairs, reserves, ..
+        } = self.shared.context.clone();
+        if let Some(pairs) = pairs {
+            let provider = provider.clone();
+            tokio::spawn(async move {
+                if let Err(e) = pairs.sync(&provider, number.as_u64()).await {
+                    warn!("{}: {:#}", chain, e);
+                }
+            });
+        }
+        if let Some(reserves) = reserves {
+            tokio::spawn(async move {
+                if let Err(e) = reserves.sync(&provider, number.as_u64()).await {
+                    warn!("{}: {:#}", chain, e);
+                }
+            });
+        }
+    }
+
+    /// Resolves with the first fatal fetch error
+    pub async fn failed(&self) -> anyhow::Error {
+        self.shared.fatal.wait().await
+    }
+
+    /// Logs the pipeline counters and current backlog
+    pub fn report(&self) {
+        let metrics = &self.shared.context.metrics;
+        log::info!(
+            "{}: {} received, {} dropped, {:?} blocked, {}/{} queued, {} in flight, {} fetched, {} retried, {} missing, {} failed, {} decoded",
+            self.chain,
+            Metrics::get(&metrics.received),
+            Metrics::get(&metrics.dropped),
+            Duration::from_micros(Metrics::get(&metrics.blocked)),
+            self.queue.len(),
+            self.queue.capacity,
+            self.concurrency - self.fetches.available_permits(),
+            Metrics::get(&metrics.fetched),
+            Metrics::get(&metrics.retried),
+            Metrics::get(&metrics.missing),
+            Metrics::get(&metrics.failed),
+            Metrics::get(&metrics.decoded),
+        );
+    }
+
+    /// Cancels outstanding fetches, returning the number of hashes left unfetched
+    pub fn stop(self) -> usize {
+        self.queue.close();
+        self.fetcher.abort();
+        self.queue.len() + self.concurrency - self.fetches.available_permits()
+    }
+}
+
+async fn fetch(queue: Arc<Queue<TxHash>>, fetches: Arc<Semaphore>, shared: Arc<Shared>) {
+    let mut tasks = JoinSet::new();
+    loop {
+        // Wait for a free slot first, so the backlog stays in the queue where the overflow policy applies
+        let Ok(permit) = fetches.clone().acquire_owned().await else {
+            break;
+        };
+        let Some(hash) = queue.pop().await else {
+            break;
+        };
+
+        let shared = shared.clone();
+        tasks.spawn(async move {
+            let _permit = permit;
+            shared.fetch(hash).await;
+        });
+
+        // Reap finished fetches so the set does not grow with the stream
+        while tasks.try_join_next().is_some() {}
+    }
+    while tasks.join_next().await.is_some() {}
+}
+
+fn is_routed(routers: &[Router], tx: &Transaction) -> bool {
+    tx.to
+        .is_some_and(|to| routers.iter().any(|router| router.address == to))
+}
+
+async fn decode(
+    chain: Chain,
+    provider: Provider<Ws>,
+    routers: Arc<Vec<Router>>,
+    mut transactions: mpsc::Receiver<Transaction>,
+    context: Context,
+) {
+    while let Some(tx) = transactions.recv().await {
+        let Some(router) = tx
+            .to
+            .and_then(|to| routers.iter().find(|router| router.address == to))
+        else {
+            continue;
+        };
+
+        debug!("Transaction to: {}", router.name);
+        match crate::decoder::decode(router, &tx) {
+            Ok(Some(mut call)) => {
+                Metrics::add(&context.metrics.decoded, 1);
+                pairs::resolve(context.pairs.as_deref(), router, &mut call.intents);
+                if let Some(reserves) = &context.reserves {
+                    let untracked = reserves.attach(&mut call.intents);
+                    if !untracked.is_empty() {
+                        tokio::spawn(reserves.clone().track(provider.clone(), untracked));
+                    }
+                    for intent in &mut call.intents {
+                        if let Intent::Swap(swap) = intent {
+                            swap.quote = amm::v2::quote(swap);
+                        }
+                    }
+                }
+                context.inclusions.track(tx.hash);
+                let event = RouterEvent::new(chain, &tx, router, call);
+                let sandwiches = context
+                    .sandwiches
+                    .as_ref()
+                    .map(|sandwiches| sandwiches.detect(&event))
+                    .unwrap_or_default();
+                let arbitrages = match (&context.arbitrage, &context.pairs, &context.reserves) {
+                    (Some(arbitrage), Some(pairs), Some(reserves)) => {
+                        // Pairs of the round trips are followed from now on, for later swaps
+                        let untracked = RefCell::new(vec![]);
+                        let arbitrages = arbitrage.detect(&event, pairs, |pool| {
+                            let latest = reserves.touch(pool);
+                            if latest.is_none() {
+                                untracked.borrow_mut().push(pool);
+                            }
+                            latest
+                        });
+                        let untracked = untracked.into_inner();
+                        if !untracked.is_empty() {
+                            tokio::spawn(reserves.clone().track(provider.clone(), untracked));
+                        }
+                        arbitrages
+                    }
+                    _ => vec![],
+                };
+                context
+                    .sinks
+                    .dispatch(WatcherEvent::Router(Box::new(event)));
+                for sandwich in sandwiches {
+                    debug!(
+                        "{}: sandwich of {:?} nets {}",
+                        chain, sandwich.hash, sandwich.net_profit
+                    );
+                    context
+                        .sinks
+                        .dispatch(WatcherEvent::SandwichOpportunity(Box::new(sandwich)));
+                }
+                for arbitrage in arbitrages {
+                    debug!(
+                        "{}: arbitrage behind {:?} gains {}",
+                        chain, arbitrage.hash, arbitrage.profit
+                    );
+                    context
+                        .sinks
+                        .dispatch(WatcherEvent::ArbitrageOpportunity(Box::new(arbitrage)));
+                }
+            }
+            Ok(None) => debug!("Ignoring non-swap call {:?}", tx.hash),
+            Err(e) => warn!("Could not decode {:?}: {}", tx.hash, e),
+        }
+    }
+}
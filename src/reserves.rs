@@ -0,0 +1,362 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use anyhow::{bail, Context, Result};
+use ethers::{
+    abi::{decode, ParamType},
+    addressbook::Chain,
+    providers::{Middleware, Provider, Ws},
+    types::{Address, Bytes, Filter, Log, TransactionRequest},
+};
+use log::{debug, info, warn};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    config::ReservesConfig,
+    model::{Intent, Reserves},
+};
+
+/// Event emitted by V2 pairs whenever their reserves change
+const SYNC: &str = "Sync(uint112,uint112)";
+/// Selector of `getReserves()`
+const GET_RESERVES: [u8; 4] = [0x09, 0x02, 0xf1, 0xac];
+/// Pairs whose `Sync` logs are fetched by a single `eth_getLogs` request
+const PAIRS_PER_FILTER: usize = 1000;
+
+#[derive(Debug, Default)]
+struct State {
+    /// Last block whose `Sync` logs were applied
+    synced_to: Option<u64>,
+    /// Reserves of each tracked pair, oldest first
+    pairs: HashMap<Address, VecDeque<Reserves>>,
+    /// Last block at which a pending swap traded against each tracked pair
+    touched: HashMap<Address, u64>,
+    /// Last `Sync` of every followed pair in each recent block, replayed onto pairs
+    /// seeded at an earlier block than the logs already applied
+    recent: VecDeque<(u64, HashMap<Address, Reserves>)>,
+}
+
+/// Current and recent reserves of V2 pairs
+///
+/// Pairs are seeded with `getReserves` when a pending swap first trades against
+/// them and then follow their `Sync` logs in every mined block, until no swap has
+/// touched them for `ttl` blocks. Chain reorganizations are not rolled back, the
+/// next `Sync` of a pair corrects it
+#[derive(Debug)]
+pub struct ReserveTracker {
+    chain: Chain,
+    /// Blocks of history kept per pair
+    history: u64,
+    /// Blocks a pair is followed after the last swap that touched it
+    ttl: u64,
+    seeds: Arc<Semaphore>,
+    state: RwLock<State>,
+    /// Pairs with a `getReserves` call in flight
+    seeding: Mutex<HashSet<Address>>,
+    /// Held while applying logs, so that overlapping syncs skip instead of queueing
+    syncing: tokio::sync::Mutex<()>,
+}
+
+impl ReserveTracker {
+    pub fn new(chain: Chain, config: &ReservesConfig) -> Self {
+        Self {
+            chain,
+            history: config.history,
+            ttl: config.ttl,
+            seeds: Arc::new(Semaphore::new(config.seed_concurrency)),
+            state: RwLock::default(),
+            seeding: Mutex::default(),
+            syncing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Reserves of `pair` as of the end of `block`, or of the latest block seen.
+    /// `None` if the pair is not tracked or `block` is older than the history kept
+    pub fn snapshot(&self, pair: Address, block: Option<u64>) -> Option<Reserves> {
+        let state = self.read();
+        let history = state.pairs.get(&pair)?;
+        match block {
+            Some(block) => history
+                .iter()
+                .rev()
+                .find(|reserves| reserves.block_number <= block)
+                .copied(),
+            None => history.back().copied(),
+        }
+    }
+
+    /// Latest reserves of `pair`, which is then followed for another `ttl` blocks.
+    /// `None` if the pair is not tracked
+    pub fn touch(&self, pair: Address) -> Option<Reserves> {
+        let reserves = self.snapshot(pair, None)?;
+        let mut state = self.write();
+        let synced_to = state.synced_to.unwrap_or_default();
+        if let Some(touched) = state.touched.get_mut(&pair) {
+            *touched = (*touched).max(synced_to);
+        }
+        Some(reserves)
+    }
+
+    /// Fills in the reserves of the pools of each V2 swap among `intents`, as of the
+    /// latest block seen, returning the pools that are not tracked yet
+    pub fn attach(&self, intents: &mut [Intent]) -> Vec<Address> {
+        let mut untracked = vec![];
+        for intent in intents {
+            let Intent::Swap(swap) = intent else {
+                continue;
+            };
+            let reserves: Vec<_> = swap
+                .pools
+                .iter()
+                .map(|pool| {
+                    let reserves = self.touch(*pool);
+                    if reserves.is_none() {
+                        untracked.push(*pool);
+                    }
+                    reserves
+                })
+                .collect();
+            swap.reserves = reserves
+                .into_iter()
+                .collect::<Option<_>>()
+                .unwrap_or_default();
+        }
+        untracked
+    }
+
+    /// Seeds the reserves of those `pairs` not tracked yet
+    pub async fn track(self: Arc<Self>, provider: Provider<Ws>, pairs: Vec<Address>) {
+        let pairs: Vec<_> = {
+            let state = self.read();
+            let mut seeding = self.seeding.lock().unwrap_or_else(|e| e.into_inner());
+            pairs
+                .into_iter()
+                .filter(|pair| !state.pairs.contains_key(pair) && seeding.insert(*pair))
+                .collect()
+        };
+        if pairs.is_empty() {
+            return;
+        }
+        if pairs.len() > 1 {
+            info!("{}: seeding reserves of {} pairs", self.chain, pairs.len());
+        }
+
+        let mut seeds = JoinSet::new();
+        for pair in pairs {
+            let Ok(permit) = self.seeds.clone().acquire_owned().await else {
+                break;
+            };
+            let (tracker, provider) = (self.clone(), provider.clone());
+            seeds.spawn(async move {
+                let _permit = permit;
+                if let Err(e) = tracker.seed(&provider, pair).await {
+                    debug!("{}: {:#}", tracker.chain, e);
+                }
+                tracker
+                    .seeding
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&pair);
+            });
+            while seeds.try_join_next().is_some() {}
+        }
+        while seeds.join_next().await.is_some() {}
+    }
+
+    async fn seed(&self, provider: &Provider<Ws>, pair: Address) -> Result<()> {
+        // Seeding at the last block applied lets the following logs pick up from there.
+        // Reading it with no sync in flight makes sure the logs after it are fetched
+        // for this pair, which `track` marked as seeding
+        let synced_to = {
+            let _syncing = self.syncing.lock().await;
+            self.read().synced_to
+        };
+        let block = match synced_to {
+            Some(block) => block,
+            None => provider.get_block_number().await?.as_u64(),
+        };
+        let call = TransactionRequest::new()
+            .to(pair)
+            .data(Bytes::from(GET_RESERVES.to_vec()));
+        let output = provider
+            .call(&call.into(), Some(block.into()))
+            .await
+            .with_context(|| format!("Could not get reserves of {:?}", pair))?;
+        let values = decode(
+            &[
+                ParamType::Uint(112),
+                ParamType::Uint(112),
+                ParamType::Uint(32),
+            ],
+            &output,
+        )
+        .with_context(|| format!("{:?} returned malformed reserves", pair))?;
+        let [reserve0, reserve1, _] = values.as_slice() else {
+            bail!("{:?} returned malformed reserves", pair);
+        };
+
+        let seeded = Reserves {
+            reserve0: reserve0.clone().into_uint().unwrap_or_default(),
+            reserve1: reserve1.clone().into_uint().unwrap_or_default(),
+            block_number: block,
+        };
+        let mut state = self.write();
+        state.synced_to.get_or_insert(block);
+        // Catch up with logs applied while the call was in flight
+        let mut history = VecDeque::from([seeded]);
+        history.extend(
+            state
+                .recent
+                .iter()
+                .filter(|(number, _)| *number > block)
+                .filter_map(|(_, syncs)| syncs.get(&pair).copied()),
+        );
+        state.pairs.entry(pair).or_insert(history);
+        let touched = state.touched.entry(pair).or_default();
+        *touched = (*touched).max(block);
+        Ok(())
+    }
+
+    /// Applies the `Sync` logs of the blocks mined since the last call, up to `head`.
+    /// Returns immediately if a sync is already running
+    pub async fn sync(self: &Arc<Self>, provider: &Provider<Ws>, head: u64) -> Result<()> {
+        let Ok(_syncing) = self.syncing.try_lock() else {
+            return Ok(());
+        };
+        let Some(synced_to) = self.read().synced_to else {
+            // Nothing seeded yet, there is nothing to update
+            return Ok(());
+        };
+        if head <= synced_to {
+            return Ok(());
+        }
+
+        // Reserves missing more blocks than the history are stale, start over
+        if head - synced_to > self.history {
+            let stale: Vec<_> = {
+                let mut state = self.write();
+                let stale = state.pairs.keys().copied().collect();
+                *state = State::default();
+                stale
+            };
+            warn!(
+                "{}: missed {} blocks of reserve updates, reseeding {} pairs",
+                self.chain,
+                head - synced_to,
+                stale.len()
+            );
+            tokio::spawn(self.clone().track(provider.clone(), stale));
+            return Ok(());
+        }
+
+        // Only the pairs tracked or being seeded are followed
+        let followed: HashSet<Address> = {
+            let state = self.read();
+            let seeding = self.seeding.lock().unwrap_or_else(|e| e.into_inner());
+            state.pairs.keys().chain(seeding.iter()).copied().collect()
+        };
+        let addresses: Vec<_> = followed.iter().copied().collect();
+        let mut logs = vec![];
+        for pairs in addresses.chunks(PAIRS_PER_FILTER) {
+            let filter = Filter::new()
+                .address(pairs.to_vec())
+                .event(SYNC)
+                .from_block(synced_to + 1)
+                .to_block(head);
+            logs.extend(provider.get_logs(&filter).await.with_context(|| {
+                format!("Could not fetch reserve updates up to block {}", head)
+            })?);
+        }
+
+        // Logs of a pair come in block and log order, so the last one per pair wins.
+        // Nodes ignoring the address filter do not fill the history with other pairs
+        let mut blocks: Vec<(u64, HashMap<Address, Reserves>)> = (synced_to + 1..=head)
+            .map(|number| (number, HashMap::new()))
+            .collect();
+        for log in &logs {
+            if !followed.contains(&log.address) {
+                continue;
+            }
+            let Some(reserves) = parse(log) else {
+                continue;
+            };
+            let index = reserves.block_number.checked_sub(synced_to + 1);
+            if let Some((_, syncs)) = index.and_then(|index| blocks.get_mut(index as usize)) {
+                syncs.insert(log.address, reserves);
+            }
+        }
+
+        let mut state = self.write();
+        let oldest = head.saturating_sub(self.history);
+        for (number, syncs) in blocks {
+            for (pair, reserves) in &syncs {
+                if let Some(history) = state.pairs.get_mut(pair) {
+                    history.push_back(*reserves);
+                }
+            }
+            debug!(
+                "{}: {} reserve updates in block {}",
+                self.chain,
+                syncs.len(),
+                number
+            );
+            state.recent.push_back((number, syncs));
+        }
+        while state
+            .recent
+            .front()
+            .is_some_and(|(number, _)| *number <= oldest)
+        {
+            state.recent.pop_front();
+        }
+        // Keep the latest reserves of quiet pairs however old they are
+        for history in state.pairs.values_mut() {
+            while history.len() > 1 && history[1].block_number <= oldest {
+                history.pop_front();
+            }
+        }
+        // Stop following pairs no swap has traded against for `ttl` blocks
+        let expired: Vec<_> = state
+            .touched
+            .iter()
+            .filter(|(_, touched)| head.saturating_sub(**touched) > self.ttl)
+            .map(|(pair, _)| *pair)
+            .collect();
+        for pair in &expired {
+            state.pairs.remove(pair);
+            state.touched.remove(pair);
+        }
+        if !expired.is_empty() {
+            debug!(
+                "{}: stopped following {} idle pairs",
+                self.chain,
+                expired.len()
+            );
+        }
+        state.synced_to = Some(head);
+        Ok(())
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Reads a `Sync` log, both reserves being in the data
+fn parse(log: &Log) -> Option<Reserves> {
+    let values = decode(&[ParamType::Uint(112), ParamType::Uint(112)], &log.data).ok()?;
+    let [reserve0, reserve1] = values.as_slice() else {
+        return None;
+    };
+    Some(Reserves {
+        reserve0: reserve0.clone().into_uint()?,
+        reserve1: reserve1.clone().into_uint()?,
+        block_number: log.block_number?.as_u64(),
+    })
+}
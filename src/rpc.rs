@@ -0,0 +1,74 @@
+use ethers::providers::{ProviderError, RpcError};
+use reqwest::StatusCode;
+
+/// JSON-RPC `method not found`
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC `invalid params`
+const INVALID_PARAMS: i64 = -32602;
+/// EIP-1474 `limit exceeded`, returned by rate limited endpoints
+const LIMIT_EXCEEDED: i64 = -32005;
+
+/// How the watcher should react to a failed RPC request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Timeouts, rate limits, dropped connections: worth retrying
+    Transient,
+    /// The transaction is unknown to the node, usually already mined or dropped
+    NotFound,
+    /// The node answered but the request or its response is unusable, e.g. an
+    /// unsupported transaction type. Retrying will not help, but the watcher can go on
+    Rejected,
+    /// The node cannot serve the watcher at all
+    Fatal,
+}
+
+pub fn classify(error: &ProviderError) -> ErrorClass {
+    match error {
+        ProviderError::JsonRpcClientError(_) => {}
+        ProviderError::SerdeJson(_) | ProviderError::HexError(_) => return ErrorClass::Rejected,
+        ProviderError::HTTPError(e)
+            if e.status().is_some_and(|status| {
+                status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+            }) =>
+        {
+            return ErrorClass::Fatal
+        }
+        ProviderError::UnsupportedRPC | ProviderError::UnsupportedNodeClient => {
+            return ErrorClass::Fatal
+        }
+        _ => return ErrorClass::Transient,
+    }
+
+    if error.as_serde_error().is_some() {
+        return ErrorClass::Rejected;
+    }
+    // Anything else that is not an error response is a transport failure
+    let Some(response) = error.as_error_response() else {
+        return ErrorClass::Transient;
+    };
+
+    let message = response.message.to_lowercase();
+    match response.code {
+        METHOD_NOT_FOUND => ErrorClass::Fatal,
+        LIMIT_EXCEEDED => ErrorClass::Transient,
+        _ if [
+            "unauthorized",
+            "forbidden",
+            "invalid api key",
+            "not allowed",
+        ]
+        .iter()
+        .any(|pattern| message.contains(pattern)) =>
+        {
+            ErrorClass::Fatal
+        }
+        _ if ["not found", "unknown transaction"]
+            .iter()
+            .any(|pattern| message.contains(pattern)) =>
+        {
+            ErrorClass::NotFound
+        }
+        INVALID_PARAMS => ErrorClass::Rejected,
+        _ => ErrorClass::Transient,
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::addressbook::Chain;
+use log::warn;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{
+        mpsc::{self, error::TrySendError},
+        watch, Mutex,
+    },
+    task::JoinSet,
+};
+
+use crate::{
+    config::{EventKind, SinkConfig, SinkFilter, SinkKind},
+    format::Format,
+    model::WatcherEvent,
+};
+
+mod nats;
+#[cfg(feature = "scylla")]
+mod scylla;
+
+pub use self::nats::NatsSink;
+#[cfg(feature = "scylla")]
+pub use self::scylla::ScyllaSink;
+
+/// A destination for watcher events
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Name of the sink, used in logs
+    fn name(&self) -> &str;
+
+    async fn send(&self, event: &WatcherEvent) -> Result<()>;
+
+    /// Delivers whatever the sink still buffers, called once on shutdown
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Prints events on stdout
+pub struct StdoutSink {
+    format: Format,
+    /// Column titles still to print before the first event
+    header: Mutex<Option<String>>,
+}
+
+impl StdoutSink {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            header: Mutex::new(format.header()),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for StdoutSink {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    async fn send(&self, event: &WatcherEvent) -> Result<()> {
+        let output = self.format.render(event)?;
+        if let Some(header) = self.header.lock().await.take() {
+            println!("{}", header);
+        }
+        println!("{}", output);
+        Ok(())
+    }
+}
+
+/// Appends events to a file, flushing after each one
+pub struct FileSink {
+    name: String,
+    format: Format,
+    /// The file, and the column titles still to write before the first event
+    file: Mutex<(File, Option<String>)>,
+}
+
+impl FileSink {
+    pub async fn open(path: PathBuf, format: Format) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("Could not open {}", path.display()))?;
+        Ok(Self {
+            name: format!("file {}", path.display()),
+            format,
+            file: Mutex::new((file, format.header())),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send(&self, event: &WatcherEvent) -> Result<()> {
+        let mut output = String::new();
+        let (file, header) = &mut *self.file.lock().await;
+        if let Some(header) = header.take() {
+            output.push_str(&header);
+            output.push('\n');
+        }
+        output.push_str(&self.format.render(event)?);
+        output.push('\n');
+        file.write_all(output.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Events a sink receives, with chain names resolved to ids
+struct Filter {
+    events: Vec<EventKind>,
+    chains: Vec<u64>,
+    routers: Vec<String>,
+    functions: Vec<String>,
+}
+
+impl Filter {
+    fn new(filter: &SinkFilter) -> Result<Self> {
+        Ok(Self {
+            events: filter.events.clone(),
+            chains: filter
+                .chains
+                .iter()
+                .map(|chain| Ok(chain.parse::<Chain>()?.into()))
+                .collect::<Result<_>>()?,
+            routers: filter.routers.clone(),
+            functions: filter.functions.clone(),
+        })
+    }
+
+    fn matches(&self, event: &WatcherEvent) -> bool {
+        let kind = match event {
+            WatcherEvent::Router(_) => EventKind::Router,
+            WatcherEvent::Inclusion(_) => EventKind::Inclusion,
+            WatcherEvent::SandwichOpportunity(_) => EventKind::SandwichOpportunity,
+            WatcherEvent::ArbitrageOpportunity(_) => EventKind::ArbitrageOpportunity,
+        };
+        if !self.events.is_empty() && !self.events.contains(&kind) {
+            return false;
+        }
+        if !self.chains.is_empty() && !self.chains.contains(&event.chain_id()) {
+            return false;
+        }
+
+        let WatcherEvent::Router(event) = event else {
+            return true;
+        };
+        (self.routers.is_empty() || self.routers.contains(&event.router.name))
+            && (self.functions.is_empty() || self.functions.contains(&event.function))
+    }
+}
+
+/// A sink fed through its own queue and task, so a slow or failing
+/// sink never holds up the watcher or the other sinks
+struct Handle {
+    name: String,
+    filter: Filter,
+    events: mpsc::Sender<Arc<WatcherEvent>>,
+    dropped: AtomicU64,
+}
+
+/// Delivers every event to each sink whose filter matches it
+pub struct Sinks {
+    handles: Vec<Handle>,
+    /// Set on shutdown, after which the sink tasks drain their queues and stop
+    closing: watch::Sender<bool>,
+    tasks: Mutex<JoinSet<()>>,
+}
+
+impl Sinks {
+    /// Connects the configured sinks, falling back to stdout when none is configured
+    ///
+    /// `format` overrides the format of stdout sinks
+    pub async fn connect(configs: &[SinkConfig], format: Option<Format>) -> Result<Self> {
+        let mut handles = vec![];
+        let (closing, _) = watch::channel(false);
+        let mut tasks = JoinSet::new();
+        if configs.is_empty() {
+            handles.push(Self::spawn(
+                &mut tasks,
+                closing.subscribe(),
+                Box::new(StdoutSink::new(format.unwrap_or_default())),
+                Filter::new(&SinkFilter::default())?,
+                1024,
+            ));
+        }
+
+        for config in configs {
+            let sink: Box<dyn Sink> = match &config.kind {
+                SinkKind::Stdout { format: configured } => {
+                    Box::new(StdoutSink::new(format.unwrap_or(*configured)))
+                }
+                SinkKind::File { path, format } => {
+                    Box::new(FileSink::open(path.clone(), *format).await?)
+                }
+                SinkKind::Nats(nats) => Box::new(NatsSink::connect(nats).await?),
+                #[cfg(feature = "scylla")]
+                SinkKind::Scylla(scylla) => Box::new(ScyllaSink::connect(scylla).await?),
+                #[cfg(not(feature = "scylla"))]
+                SinkKind::Scylla(_) => anyhow::bail!("Built without the scylla feature"),
+            };
+            handles.push(Self::spawn(
+                &mut tasks,
+                closing.subscribe(),
+                sink,
+                Filter::new(&config.filter)?,
+                config.capacity,
+            ));
+        }
+        Ok(Self {
+            handles,
+            closing,
+            tasks: Mutex::new(tasks),
+        })
+    }
+
+    fn spawn(
+        tasks: &mut JoinSet<()>,
+        mut closing: watch::Receiver<bool>,
+        sink: Box<dyn Sink>,
+        filter: Filter,
+        capacity: usize,
+    ) -> Handle {
+        let name = sink.name().to_string();
+        let (events, mut receiver) = mpsc::channel::<Arc<WatcherEvent>>(capacity);
+        tasks.spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = receiver.recv() => event,
+                    // Refuse new events but deliver those already queued
+                    _ = closing.changed() => {
+                        receiver.close();
+                        receiver.recv().await
+                    }
+                };
+                let Some(event) = event else {
+                    break;
+                };
+                if let Err(e) = sink.send(&event).await {
+                    warn!("{} sink failed: {:#}", sink.name(), e);
+                }
+            }
+            if let Err(e) = sink.close().await {
+                warn!("{} sink failed to close: {:#}", sink.name(), e);
+            }
+        });
+
+        Handle {
+            name,
+            filter,
+            events,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues `event` for every matching sink, dropping it for sinks that are behind
+    pub fn dispatch(&self, event: WatcherEvent) {
+        let event = Arc::new(event);
+        for handle in &self.handles {
+            if !handle.filter.matches(&event) {
+                continue;
+            }
+            if let Err(TrySendError::Full(_)) = handle.events.try_send(event.clone()) {
+                let dropped = handle.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                // Log the first drop and then every thousandth, not every event
+                if dropped % 1000 == 1 {
+                    warn!(
+                        "{} sink is falling behind, {} events dropped",
+                        handle.name, dropped
+                    );
+                }
+            }
+        }
+    }
+
+    /// Closes the sink queues and waits for the events already queued to be delivered.
+    /// Events dispatched afterwards are dropped
+    pub async fn close(&self) {
+        self.closing.send_replace(true);
+        let mut tasks = self.tasks.lock().await;
+        while tasks.join_next().await.is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+
+    use super::*;
+    use crate::model::{InclusionEvent, InclusionStatus};
+
+    #[tokio::test]
+    async fn delivers_queued_events_on_close() {
+        let path = std::env::temp_dir().join(format!("sink-close-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = SinkConfig {
+            kind: SinkKind::File {
+                path: path.clone(),
+                format: Format::Jsonl,
+            },
+            filter: SinkFilter::default(),
+            capacity: 16,
+        };
+        let sinks = Sinks::connect(&[config], None).await.unwrap();
+
+        for block_number in 0..10 {
+            sinks.dispatch(WatcherEvent::Inclusion(InclusionEvent {
+                chain_id: 1,
+                hash: H256::repeat_byte(1),
+                status: InclusionStatus::Included,
+                block_number,
+            }));
+        }
+        sinks.close().await;
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.lines().count(), 10);
+        assert!(written.ends_with("\n"));
+    }
+}
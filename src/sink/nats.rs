@@ -0,0 +1,387 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use async_nats::{
+    jetstream::{self, context::Publish, stream},
+    Client, ConnectOptions, Event,
+};
+use async_trait::async_trait;
+use log::{debug, warn};
+
+use super::Sink;
+use crate::{
+    config::{JetStreamConfig, NatsConfig},
+    model::{chain_name, WatcherEvent},
+};
+
+/// Publishes events as JSON to `{prefix}.{chain}.{router}.{function}`, or
+/// `{prefix}.{chain}.inclusion.{status}` for inclusion events and
+/// `{prefix}.{chain}.sandwich.{router}` and `{prefix}.{chain}.arbitrage.{router}`
+/// for opportunities
+///
+/// The client reconnects on its own and buffers publishes while disconnected.
+/// In JetStream mode every publish waits for the stream to acknowledge it
+#[derive(Clone, Debug)]
+pub struct NatsSink {
+    client: Client,
+    jetstream: Option<jetstream::Context>,
+    prefix: String,
+}
+
+impl NatsSink {
+    pub async fn connect(config: &NatsConfig) -> Result<Self> {
+        let options = if let Some(credentials) = &config.credentials {
+            ConnectOptions::with_credentials_file(credentials.clone())
+                .await
+                .with_context(|| format!("Could not read {}", credentials.display()))?
+        } else if let Some(token) = config.token()? {
+            ConnectOptions::with_token(token)
+        } else if let (Some(user), Some(password)) = (config.user()?, config.password()?) {
+            ConnectOptions::with_user_and_password(user, password)
+        } else {
+            ConnectOptions::new()
+        };
+
+        let url = config.url()?;
+        let client = options
+            .name("uniswap-watcher")
+            .event_callback(|event| async move {
+                match event {
+                    Event::Connected => log::info!("NATS connected"),
+                    event => warn!("NATS {}", event),
+                }
+            })
+            .connect(url.as_str())
+            .await
+            .with_context(|| format!("Could not connect to NATS at {}", url))?;
+
+        let jetstream = match &config.jetstream {
+            Some(settings) => {
+                let context = jetstream::new(client.clone());
+                ensure_stream(&context, settings, &config.subject_prefix).await?;
+                Some(context)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            client,
+            jetstream,
+            prefix: config.subject_prefix.clone(),
+        })
+    }
+
+    fn subject(&self, event: &WatcherEvent) -> String {
+        let chain = chain_name(event.chain_id());
+        match event {
+            WatcherEvent::Router(event) => format!(
+                "{}.{}.{}.{}",
+                self.prefix,
+                token(&chain),
+                token(&event.router.name),
+                token(&event.function)
+            ),
+            WatcherEvent::Inclusion(event) => format!(
+                "{}.{}.inclusion.{}",
+                self.prefix,
+                token(&chain),
+                token(&format!("{:?}", event.status))
+            ),
+            WatcherEvent::SandwichOpportunity(event) => format!(
+                "{}.{}.sandwich.{}",
+                self.prefix,
+                token(&chain),
+                token(&event.router)
+            ),
+            WatcherEvent::ArbitrageOpportunity(event) => format!(
+                "{}.{}.arbitrage.{}",
+                self.prefix,
+                token(&chain),
+                token(&event.router)
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for NatsSink {
+    fn name(&self) -> &str {
+        "nats"
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.client.flush().await?;
+        Ok(())
+    }
+
+    async fn send(&self, event: &WatcherEvent) -> Result<()> {
+        let subject = self.subject(event);
+        let payload = serde_json::to_vec(event)?;
+        let Some(jetstream) = &self.jetstream else {
+            self.client.publish(subject, payload.into()).await?;
+            return Ok(());
+        };
+
+        // Deduplicates replays of the same transaction, and of the same outcome
+        let message_id = match event {
+            WatcherEvent::Router(event) => format!("{:?}", event.transaction.hash),
+            WatcherEvent::Inclusion(event) => format!("{:?}-{:?}", event.hash, event.status),
+            WatcherEvent::SandwichOpportunity(event) => format!("{:?}-sandwich", event.hash),
+            // A swap can open several round trips
+            WatcherEvent::ArbitrageOpportunity(event) => format!(
+                "{:?}-arbitrage-{}",
+                event.hash,
+                event
+                    .pools
+                    .iter()
+                    .map(|pool| format!("{:x}", pool))
+                    .collect::<Vec<_>>()
+                    .join("-")
+            ),
+        };
+        let publish = Publish::build()
+            .payload(payload.into())
+            .message_id(&message_id);
+        let ack = jetstream
+            .send_publish(subject, publish)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+        if ack.duplicate {
+            debug!("{} already in stream {}", message_id, ack.stream);
+        }
+        Ok(())
+    }
+}
+
+/// Creates the configured stream, or checks that an existing one captures the
+/// watcher subjects and brings its limits in line with the configuration
+async fn ensure_stream(
+    context: &jetstream::Context,
+    settings: &JetStreamConfig,
+    prefix: &str,
+) -> Result<()> {
+    let subjects = format!("{}.>", prefix);
+    let wanted = stream::Config {
+        name: settings.stream.clone(),
+        subjects: vec![subjects.clone()],
+        max_age: Duration::from_secs(settings.max_age),
+        max_bytes: limit(settings.max_bytes),
+        max_messages: limit(settings.max_messages),
+        retention: settings.retention,
+        storage: settings.storage,
+        num_replicas: settings.replicas,
+        duplicate_window: Duration::from_secs(settings.duplicate_window).as_nanos() as i64,
+        ..Default::default()
+    };
+
+    let stream = context
+        .get_or_create_stream(wanted.clone())
+        .await
+        .map_err(|e| anyhow::anyhow!(e))
+        .with_context(|| format!("Could not create stream {}", settings.stream))?;
+    let existing = &stream.cached_info().config;
+
+    if !existing
+        .subjects
+        .iter()
+        .any(|subject| subject == &subjects || subject == ">")
+    {
+        bail!(
+            "Stream {} does not capture {} (subjects {:?})",
+            settings.stream,
+            subjects,
+            existing.subjects
+        );
+    }
+    // Neither can be changed once the stream exists
+    if existing.retention != wanted.retention || existing.storage != wanted.storage {
+        bail!(
+            "Stream {} has {:?} retention and {:?} storage, configured {:?} and {:?}",
+            settings.stream,
+            existing.retention,
+            existing.storage,
+            wanted.retention,
+            wanted.storage
+        );
+    }
+
+    if existing.max_age != wanted.max_age
+        || existing.max_bytes != wanted.max_bytes
+        || existing.max_messages != wanted.max_messages
+        || existing.num_replicas != wanted.num_replicas
+        || existing.duplicate_window != wanted.duplicate_window
+    {
+        log::info!("Updating limits of stream {}", settings.stream);
+        let updated = stream::Config {
+            subjects: existing.subjects.clone(),
+            ..wanted
+        };
+        context
+            .update_stream(&updated)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+            .with_context(|| format!("Could not update stream {}", settings.stream))?;
+    }
+    Ok(())
+}
+
+/// JetStream encodes "no limit" as -1
+fn limit(value: u64) -> i64 {
+    if value == 0 {
+        -1
+    } else {
+        value as i64
+    }
+}
+
+/// Lowercases `value` and replaces anything but letters, digits, `-` and `_`
+/// with single dashes, so names like `Uniswap V3: Router 2` make valid subject tokens
+fn token(value: &str) -> String {
+    let mut token = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            token.push(c.to_ascii_lowercase());
+        } else if !token.is_empty() && !token.ends_with('-') {
+            token.push('-');
+        }
+    }
+    token.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::{
+        abi::Abi,
+        addressbook::Chain,
+        types::{Address, Transaction, U256},
+    };
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+    use crate::{
+        contracts::Router,
+        decoder::DecodedCall,
+        model::{Intent, RouterEvent, SwapDirection, SwapIntent, SwapKind},
+    };
+
+    /// Minimal stand-in for nats-server: greets with INFO, answers PING
+    /// and forwards every PUB it receives
+    async fn serve(listener: TcpListener, published: mpsc::UnboundedSender<(String, Vec<u8>)>) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(b"INFO {\"server_id\":\"test\",\"version\":\"2.9.0\",\"proto\":1,\"max_payload\":1048576,\"headers\":true}\r\n")
+            .await
+            .unwrap();
+
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        while reader.read_line(&mut line).await.unwrap() > 0 {
+            let op: Vec<&str> = line.split_whitespace().collect();
+            match op.first().copied() {
+                Some("PING") => writer.write_all(b"PONG\r\n").await.unwrap(),
+                Some("PUB") => {
+                    let length: usize = op.last().unwrap().parse().unwrap();
+                    let mut payload = vec![0; length + 2];
+                    reader.read_exact(&mut payload).await.unwrap();
+                    payload.truncate(length);
+                    published.send((op[1].to_string(), payload)).unwrap();
+                }
+                _ => {}
+            }
+            line.clear();
+        }
+    }
+
+    fn event() -> WatcherEvent {
+        let router = Router {
+            address: "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45"
+                .parse()
+                .unwrap(),
+            abi: Abi::default(),
+            name: "Uniswap V3: Router 2".to_string(),
+            version: 3,
+            factory: vec![],
+            wrapped_native: None,
+        };
+        let call = DecodedCall {
+            function: "exactInputSingle".to_string(),
+            intents: vec![Intent::Swap(SwapIntent {
+                function: "exactInputSingle".to_string(),
+                kind: SwapKind::ExactIn,
+                direction: SwapDirection::TokenToToken,
+                native_eth: false,
+                path: vec![Address::repeat_byte(1), Address::repeat_byte(2)],
+                amount_in: Some(U256::exp10(18)),
+                amount_out: U256::one(),
+                recipient: None,
+                deadline: None,
+                fee_on_transfer: false,
+                version: 3,
+                fees: vec![500],
+                pools: vec![],
+                reserves: vec![],
+                quote: None,
+            })],
+        };
+        WatcherEvent::Router(Box::new(RouterEvent::new(
+            Chain::Mainnet,
+            &Transaction::default(),
+            &router,
+            call,
+        )))
+    }
+
+    #[test]
+    fn subject_tokens_are_sanitized() {
+        assert_eq!(token("Uniswap V3: Router 2"), "uniswap-v3-router-2");
+        assert_eq!(token("V2_SWAP_EXACT_IN"), "v2_swap_exact_in");
+        assert_eq!(token("mainnet"), "mainnet");
+    }
+
+    #[tokio::test]
+    async fn publishes_events_as_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, mut published) = mpsc::unbounded_channel();
+        tokio::spawn(serve(listener, sender));
+
+        let sink = NatsSink::connect(&NatsConfig {
+            url: format!("nats://{}", address),
+            subject_prefix: "uniswap".to_string(),
+            token: None,
+            user: None,
+            password: None,
+            credentials: None,
+            jetstream: None,
+        })
+        .await
+        .unwrap();
+        sink.send(&event()).await.unwrap();
+        sink.client.flush().await.unwrap();
+
+        let (subject, payload) = tokio::time::timeout(Duration::from_secs(5), published.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            subject,
+            "uniswap.mainnet.uniswap-v3-router-2.exactinputsingle"
+        );
+        let payload: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(payload["event"], "router");
+        assert_eq!(payload["chain_id"], 1);
+        assert_eq!(payload["function"], "exactInputSingle");
+        assert_eq!(payload["router"]["name"], "Uniswap V3: Router 2");
+        assert_eq!(payload["intents"][0]["type"], "swap");
+        assert_eq!(payload["intents"][0]["amount_in"], "1000000000000000000");
+    }
+}
@@ -0,0 +1,483 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::types::U256;
+use log::{debug, warn};
+use scylla::{
+    batch::{Batch, BatchType},
+    frame::value::ValueList,
+    prepared_statement::PreparedStatement,
+    Session, SessionBuilder,
+};
+use serde::Serialize;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinSet,
+    time::MissedTickBehavior,
+};
+
+use super::Sink;
+use crate::{
+    backoff::Backoff,
+    config::ScyllaConfig,
+    model::{InclusionEvent, Intent, RouterEvent, WatcherEvent},
+};
+
+/// Schema migrations, applied in order and recorded in `schema_migrations`
+const MIGRATIONS: &[(i32, &str, &str)] = &[
+    (
+        1,
+        "create_tables",
+        include_str!("../../migrations/scylla/0001_create_tables.cql"),
+    ),
+    (
+        2,
+        "transactions_by_hash",
+        include_str!("../../migrations/scylla/0002_transactions_by_hash.cql"),
+    ),
+];
+
+const INSERT_TRANSACTION: &str = "INSERT INTO pending_transactions (chain_id, day, seen_at, hash, sender, nonce, value, gas, gas_price, max_fee_per_gas, max_priority_fee_per_gas, router, router_name, function) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+const INSERT_HASH: &str =
+    "INSERT INTO pending_transactions_by_hash (chain_id, hash, day, seen_at) VALUES (?, ?, ?, ?)";
+const INSERT_INTENT: &str = "INSERT INTO swap_intents (chain_id, hash, position, function, kind, direction, native_eth, path, amount_in, amount_out, recipient, deadline, fee_on_transfer, version, fees) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+const INSERT_STATUS: &str = "INSERT INTO transaction_status (chain_id, hash, status, block_number, updated_at) VALUES (?, ?, ?, ?, ?)";
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+/// Times a batch is tried before its rows are dropped
+const WRITE_ATTEMPTS: u32 = 3;
+
+type TransactionRow = (
+    i64,
+    i32,
+    i64,
+    String,
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    String,
+    String,
+    String,
+);
+type IntentRow = (
+    i64,
+    String,
+    i32,
+    String,
+    String,
+    String,
+    bool,
+    Vec<String>,
+    Option<String>,
+    String,
+    Option<String>,
+    Option<String>,
+    bool,
+    i32,
+    Vec<i32>,
+);
+type HashRow = (i64, String, i32, i64);
+type StatusRow = (i64, String, String, i64, i64);
+
+enum Row {
+    Transaction(TransactionRow),
+    Hash(HashRow),
+    Intent(IntentRow),
+    Status(StatusRow),
+    /// Writes the rows queued so far right away, signalling once they are written
+    Flush(oneshot::Sender<()>),
+}
+
+struct Statements {
+    transaction: PreparedStatement,
+    hash: PreparedStatement,
+    intent: PreparedStatement,
+    status: PreparedStatement,
+}
+
+/// Writes pending router transactions, their swap intents and inclusion status to Scylla
+///
+/// Rows are queued and written by a background task whenever `batch_size` rows are
+/// waiting or `flush_interval` passes, in one unlogged batch per partition
+#[derive(Clone, Debug)]
+pub struct ScyllaSink {
+    rows: mpsc::Sender<Row>,
+}
+
+impl ScyllaSink {
+    /// Connects to the cluster, creating the keyspace and applying pending migrations
+    pub async fn connect(config: &ScyllaConfig) -> Result<Self> {
+        let mut builder = SessionBuilder::new().known_nodes(&config.nodes()?);
+        if let (Some(user), Some(password)) = (config.user()?, config.password()?) {
+            builder = builder.user(user, password);
+        }
+        let session = builder
+            .build()
+            .await
+            .context("Could not connect to Scylla")?;
+
+        session
+            .query(
+                format!(
+                    "CREATE KEYSPACE IF NOT EXISTS {} WITH replication = {{'class': 'SimpleStrategy', 'replication_factor': {}}}",
+                    config.keyspace, config.replication_factor
+                ),
+                (),
+            )
+            .await
+            .with_context(|| format!("Could not create keyspace {}", config.keyspace))?;
+        session.use_keyspace(&config.keyspace, false).await?;
+        migrate(&session).await?;
+
+        let statements = Statements {
+            transaction: session.prepare(INSERT_TRANSACTION).await?,
+            hash: session.prepare(INSERT_HASH).await?,
+            intent: session.prepare(INSERT_INTENT).await?,
+            status: session.prepare(INSERT_STATUS).await?,
+        };
+        let (rows, receiver) = mpsc::channel(config.batch_size * 10);
+        tokio::spawn(write(
+            Arc::new(session),
+            statements,
+            receiver,
+            config.batch_size,
+            Duration::from_millis(config.flush_interval),
+        ));
+
+        Ok(Self { rows })
+    }
+
+    /// Queues the transaction of `event` and its swap intents
+    async fn record(&self, event: &RouterEvent) {
+        let chain_id = event.chain_id as i64;
+        let seen_at = now();
+        let day = (seen_at / MILLIS_PER_DAY) as i32;
+        let tx = &event.transaction;
+        let hash = format!("{:?}", tx.hash);
+
+        self.send(Row::Transaction((
+            chain_id,
+            day,
+            seen_at,
+            hash.clone(),
+            format!("{:?}", tx.from),
+            tx.nonce.to_string(),
+            tx.value.to_string(),
+            tx.gas.to_string(),
+            tx.gas_price.as_ref().map(U256::to_string),
+            tx.max_fee_per_gas.as_ref().map(U256::to_string),
+            tx.max_priority_fee_per_gas.as_ref().map(U256::to_string),
+            format!("{:?}", event.router.address),
+            event.router.name.clone(),
+            event.function.clone(),
+        )))
+        .await;
+        self.send(Row::Hash((chain_id, hash.clone(), day, seen_at)))
+            .await;
+
+        for (position, intent) in event.intents.iter().enumerate() {
+            let Intent::Swap(swap) = intent else {
+                continue;
+            };
+            self.send(Row::Intent((
+                chain_id,
+                hash.clone(),
+                position as i32,
+                swap.function.clone(),
+                name(&swap.kind),
+                name(&swap.direction),
+                swap.native_eth,
+                swap.path
+                    .iter()
+                    .map(|token| format!("{:?}", token))
+                    .collect(),
+                swap.amount_in.as_ref().map(U256::to_string),
+                swap.amount_out.to_string(),
+                swap.recipient.map(|recipient| format!("{:?}", recipient)),
+                swap.deadline.as_ref().map(U256::to_string),
+                swap.fee_on_transfer,
+                swap.version.into(),
+                swap.fees.iter().map(|fee| *fee as i32).collect(),
+            )))
+            .await;
+        }
+    }
+
+    /// Queues the inclusion status of a previously recorded transaction
+    async fn record_inclusion(&self, event: &InclusionEvent) {
+        self.send(Row::Status((
+            event.chain_id as i64,
+            format!("{:?}", event.hash),
+            name(&event.status),
+            event.block_number as i64,
+            now(),
+        )))
+        .await;
+    }
+
+    async fn send(&self, row: Row) {
+        if self.rows.send(row).await.is_err() {
+            warn!("Scylla writer stopped, dropping row");
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for ScyllaSink {
+    fn name(&self) -> &str {
+        "scylla"
+    }
+
+    async fn send(&self, event: &WatcherEvent) -> Result<()> {
+        match event {
+            WatcherEvent::Router(event) => self.record(event).await,
+            WatcherEvent::Inclusion(event) => self.record_inclusion(event).await,
+            // Opportunities are derived from the recorded transactions, not stored
+            WatcherEvent::SandwichOpportunity(_) | WatcherEvent::ArbitrageOpportunity(_) => {}
+        }
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        let (done, flushed) = oneshot::channel();
+        self.send(Row::Flush(done)).await;
+        flushed.await.context("Scylla writer stopped")
+    }
+}
+
+/// Applies the migrations not yet recorded in `schema_migrations`
+async fn migrate(session: &Session) -> Result<()> {
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (version int PRIMARY KEY, name text, applied_at bigint)",
+            (),
+        )
+        .await?;
+    let applied = session
+        .query("SELECT version FROM schema_migrations", ())
+        .await?
+        .rows_typed_or_empty::<(i32,)>()
+        .map(|row| row.map(|(version,)| version))
+        .collect::<Result<HashSet<_>, _>>()?;
+
+    for (version, name, migration) in MIGRATIONS {
+        if applied.contains(version) {
+            continue;
+        }
+
+        log::info!("Applying Scylla migration {} {}", version, name);
+        for statement in statements(migration) {
+            session
+                .query(statement, ())
+                .await
+                .with_context(|| format!("Migration {} {} failed", version, name))?;
+        }
+        session
+            .query(
+                "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)",
+                (version, name, now()),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// CQL statements of a migration file, without its `--` comment lines
+fn statements(migration: &str) -> Vec<String> {
+    migration
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .split(';')
+        .map(str::trim)
+        .filter(|statement| !statement.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Collects queued rows and writes them in batches until every sink handle is dropped
+async fn write(
+    session: Arc<Session>,
+    statements: Statements,
+    mut rows: mpsc::Receiver<Row>,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut transactions = vec![];
+    let mut hashes = vec![];
+    let mut intents = vec![];
+    let mut statuses = vec![];
+    let mut flush = tokio::time::interval(flush_interval);
+    flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let mut closed = false;
+        let mut flushed = None;
+        tokio::select! {
+            row = rows.recv() => {
+                match row {
+                    Some(Row::Transaction(row)) => transactions.push(row),
+                    Some(Row::Hash(row)) => hashes.push(row),
+                    Some(Row::Intent(row)) => intents.push(row),
+                    Some(Row::Status(row)) => statuses.push(row),
+                    Some(Row::Flush(done)) => flushed = Some(done),
+                    None => closed = true,
+                }
+                // Partial batches wait for the next tick
+                let queued = transactions.len() + hashes.len() + intents.len() + statuses.len();
+                if !closed && flushed.is_none() && queued < batch_size {
+                    continue;
+                }
+            }
+            _ = flush.tick() => {}
+        }
+
+        let mut writes = JoinSet::new();
+        execute(
+            &mut writes,
+            &session,
+            &statements.transaction,
+            &mut transactions,
+            |row| (row.0, row.1),
+        );
+        execute(
+            &mut writes,
+            &session,
+            &statements.hash,
+            &mut hashes,
+            |row| (row.0, row.1.clone()),
+        );
+        execute(
+            &mut writes,
+            &session,
+            &statements.intent,
+            &mut intents,
+            |row| (row.0, row.1.clone()),
+        );
+        execute(
+            &mut writes,
+            &session,
+            &statements.status,
+            &mut statuses,
+            |row| (row.0, row.1.clone()),
+        );
+
+        let (mut written, mut failed) = (0, 0);
+        while let Some(result) = writes.join_next().await {
+            match result {
+                Ok(Ok(rows)) => written += rows,
+                Ok(Err(rows)) => failed += rows,
+                Err(e) => warn!("Scylla write task failed: {}", e),
+            }
+        }
+        if written > 0 {
+            debug!("Wrote {} rows to Scylla", written);
+        }
+        if failed > 0 {
+            warn!("Could not write {} rows to Scylla", failed);
+        }
+        if let Some(done) = flushed {
+            let _ = done.send(());
+        }
+        if closed {
+            break;
+        }
+    }
+}
+
+/// Spawns one unlogged batch of `statement` per partition of `rows` into `writes`,
+/// leaving `rows` empty
+///
+/// `partition` returns the partition key of a row, so each batch goes to a single
+/// replica set. At most `batch_size` rows are queued per flush, which bounds the
+/// number of batches in flight.
+fn execute<K, T>(
+    writes: &mut JoinSet<Result<usize, usize>>,
+    session: &Arc<Session>,
+    statement: &PreparedStatement,
+    rows: &mut Vec<T>,
+    partition: impl Fn(&T) -> K,
+) where
+    K: Eq + Hash,
+    T: ValueList + Send + Sync + 'static,
+{
+    let mut partitions: HashMap<K, Vec<T>> = HashMap::new();
+    for row in rows.drain(..) {
+        partitions.entry(partition(&row)).or_default().push(row);
+    }
+
+    for rows in partitions.into_values() {
+        let mut batch = Batch::new(BatchType::Unlogged);
+        for _ in &rows {
+            batch.append_statement(statement.clone());
+        }
+        let session = session.clone();
+        writes.spawn(async move {
+            let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(2));
+            loop {
+                match session.batch(&batch, &rows).await {
+                    Ok(_) => return Ok(rows.len()),
+                    Err(e) if backoff.attempt() + 1 < WRITE_ATTEMPTS => {
+                        debug!("Could not write a batch to Scylla, retrying: {}", e);
+                        tokio::time::sleep(backoff.next_delay()).await;
+                    }
+                    Err(e) => {
+                        debug!("Could not write a batch to Scylla: {}", e);
+                        return Err(rows.len());
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Name an enum serializes to, e.g. `exact_in`
+fn name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Current unix time in milliseconds
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_migrations_into_statements() {
+        for (version, name, migration) in MIGRATIONS {
+            let statements = statements(migration);
+            assert!(!statements.is_empty(), "{} {}", version, name);
+            for statement in statements {
+                assert!(
+                    ["CREATE ", "ALTER ", "DROP ", "INSERT ", "UPDATE "]
+                        .iter()
+                        .any(|keyword| statement.starts_with(keyword)),
+                    "{} {}: {}",
+                    version,
+                    name,
+                    statement
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    fmt,
+    future::pending,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use ethers::{
+    addressbook::Chain,
+    providers::{Middleware, Provider, ProviderError, StreamExt, SubscriptionStream, Ws},
+    types::{Block, TxHash, U64},
+};
+use log::{debug, warn};
+use tokio::time::{interval_at, sleep_until, timeout, MissedTickBehavior};
+
+use crate::{
+    analysis::{arbitrage::ArbitrageDetector, sandwich::SandwichDetector},
+    backoff::Backoff,
+    config::{ChainConfig, Config, SubscriptionMode, WatcherConfig},
+    inclusion::Inclusions,
+    pairs::PairRegistry,
+    pipeline::{Context, Metrics, Pending, Pipeline},
+    reserves::ReserveTracker,
+    rpc::{classify, ErrorClass},
+    sink::Sinks,
+};
+
+/// Why a subscription stopped delivering pending transactions
+enum Interruption {
+    Closed,
+    Stalled(Duration),
+}
+
+impl fmt::Display for Interruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interruption::Closed => write!(f, "subscription closed"),
+            Interruption::Stalled(limit) => write!(f, "no pending transaction for {:?}", limit),
+        }
+    }
+}
+
+/// Time during which no subscription was live
+struct Gap {
+    since: Instant,
+    /// Last block the node reported before the interruption, if it still answered
+    last_block: Option<U64>,
+}
+
+impl Gap {
+    async fn report(self, chain: Chain, provider: &Provider<Ws>) {
+        let duration = self.since.elapsed();
+        match (self.last_block, provider.get_block_number().await.ok()) {
+            (Some(from), Some(to)) => warn!(
+                "{}: resubscribed after {:?}, pending transactions of blocks {} to {} may have been missed",
+                chain, duration, from, to
+            ),
+            _ => warn!(
+                "{}: resubscribed after {:?}, pending transactions in between may have been missed",
+                chain, duration
+            ),
+        }
+    }
+}
+
+/// Watches the pending transactions of a single chain for calls to its routers
+///
+/// Lost, failed or stalled subscriptions are reopened on a fresh connection
+/// with exponential backoff, so a node restart only leaves a reported gap.
+/// `global` holds the settings shared by every chain
+pub async fn watch(config: ChainConfig, global: Arc<Config>, sinks: Arc<Sinks>) -> Result<()> {
+    let chain = config.chain()?;
+    let settings = &global.watcher;
+    let wrapped_native = config.wrapped_native()?;
+    let sandwiches = match (&global.sandwich, wrapped_native) {
+        (Some(sandwich), Some(wrapped_native)) => Some(Arc::new(SandwichDetector::new(
+            chain,
+            sandwich.clone(),
+            wrapped_native,
+        ))),
+        (Some(_), None) => {
+            warn!(
+                "{}: no wrapped native token known, set wrapped_native to detect sandwiches",
+                chain
+            );
+            None
+        }
+        (None, _) => None,
+    };
+    let arbitrage = match (&global.arbitrage, wrapped_native) {
+        (Some(arbitrage), Some(wrapped_native)) => Some(Arc::new(ArbitrageDetector::new(
+            chain,
+            arbitrage.clone(),
+            wrapped_native,
+        ))),
+        (Some(_), None) => {
+            warn!(
+                "{}: no wrapped native token known, set wrapped_native to detect arbitrage",
+                chain
+            );
+            None
+        }
+        (None, _) => None,
+    };
+    let ws_url = config.ws_url()?;
+    let mut backoff = settings.backoff();
+    let mut context = Context {
+        metrics: Arc::new(Metrics::default()),
+        inclusions: Arc::new(Inclusions::new(Duration::from_secs(
+            settings.inclusion_timeout,
+        ))),
+        pairs: None,
+        reserves: global
+            .reserves
+            .as_ref()
+            .map(|reserves| Arc::new(ReserveTracker::new(chain, reserves))),
+        sandwiches,
+        arbitrage,
+        sinks,
+    };
+    let mut routers = None;
+    let mut gap: Option<Gap> = None;
+
+    loop {
+        // Initialize Provider
+        let provider_ws = match Provider::<Ws>::connect(&ws_url).await {
+            Ok(provider) => provider,
+            Err(e) => {
+                reconnect(chain, &mut backoff, format!("could not connect: {}", e)).await;
+                continue;
+            }
+        };
+
+        // Routers are resolved once, on the first connection, retried until their ABIs load
+        if routers.is_none() {
+            let resolved = match config.routers(&global.abi, provider_ws.clone()).await {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    let reason = format!("could not resolve routers: {:#}", e);
+                    reconnect(chain, &mut backoff, reason).await;
+                    continue;
+                }
+            };
+            log::info!("Watching {} routers on {}", resolved.len(), chain);
+            if let Some(pairs) = &global.pairs {
+                let factories: Vec<_> = resolved
+                    .iter()
+                    .flat_map(|router| router.factory.iter().cloned())
+                    .collect();
+                context.pairs = Some(Arc::new(PairRegistry::load(chain, &factories, pairs)?));
+            }
+            routers = Some(Arc::new(resolved));
+        }
+        let routers = routers.clone().unwrap_or_default();
+
+        // New heads settle the inclusion of router transactions seen pending
+        let streams = async {
+            let tx_stream = subscribe(chain, &provider_ws, config.subscription).await?;
+            Ok((tx_stream, provider_ws.subscribe_blocks().await?))
+        };
+        let (mut tx_stream, mut blocks) = match streams.await {
+            Ok(streams) => streams,
+            Err(e) if classify(&e) == ErrorClass::Fatal => {
+                return Err(anyhow::Error::new(e).context("Could not subscribe"));
+            }
+            Err(e) => {
+                reconnect(chain, &mut backoff, format!("could not subscribe: {}", e)).await;
+                continue;
+            }
+        };
+        if let Some(gap) = gap.take() {
+            gap.report(chain, &provider_ws).await;
+        }
+
+        let pipeline = Pipeline::start(
+            chain,
+            provider_ws.clone(),
+            routers,
+            settings,
+            context.clone(),
+        );
+        let interruption = ingest(
+            &mut tx_stream,
+            &mut blocks,
+            &pipeline,
+            settings,
+            &mut backoff,
+        )
+        .await;
+        let discarded = pipeline.stop();
+        if discarded > 0 {
+            warn!("{}: discarding {} unfetched hashes", chain, discarded);
+        }
+        let interruption = interruption?;
+
+        // The node may still answer if only the subscription went quiet
+        let last_block = timeout(Duration::from_secs(5), provider_ws.get_block_number())
+            .await
+            .ok()
+            .and_then(Result::ok);
+        gap = Some(Gap {
+            since: Instant::now(),
+            last_block,
+        });
+        reconnect(chain, &mut backoff, interruption).await;
+    }
+}
+
+/// Subscribes to pending transactions, falling back to hashes in `auto` mode
+/// if the node rejects the full transaction subscription
+async fn subscribe(
+    chain: Chain,
+    provider_ws: &Provider<Ws>,
+    mode: SubscriptionMode,
+) -> Result<SubscriptionStream<'_, Ws, Pending>, ProviderError> {
+    if mode != SubscriptionMode::Hashes {
+        match provider_ws
+            .subscribe(("newPendingTransactions", true))
+            .await
+        {
+            Ok(stream) => {
+                log::info!("{}: subscribed to full pending transactions", chain);
+                return Ok(stream);
+            }
+            Err(e) if mode == SubscriptionMode::Auto => {
+                debug!("{}: full pending transactions unsupported: {}", chain, e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let stream = provider_ws.subscribe(["newPendingTransactions"]).await?;
+    log::info!("{}: subscribed to pending transaction hashes", chain);
+    Ok(stream)
+}
+
+/// Feeds items from `tx_stream` into `pipeline` until the subscription is interrupted,
+/// reporting pipeline metrics along the way. Fails on a fatal pipeline error
+async fn ingest(
+    tx_stream: &mut SubscriptionStream<'_, Ws, Pending>,
+    blocks: &mut SubscriptionStream<'_, Ws, Block<TxHash>>,
+    pipeline: &Pipeline,
+    settings: &WatcherConfig,
+    backoff: &mut Backoff,
+) -> Result<Interruption> {
+    let stall_timeout = settings.stall_timeout();
+    let mut last_seen = Instant::now();
+    let mut report = settings.metrics_interval().map(|period| {
+        let mut report = interval_at(tokio::time::Instant::now() + period, period);
+        report.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        report
+    });
+
+    loop {
+        let stalled = async {
+            match stall_timeout {
+                Some(limit) => sleep_until((last_seen + limit).into()).await,
+                None => pending().await,
+            }
+        };
+        let report_due = async {
+            match &mut report {
+                Some(report) => {
+                    report.tick().await;
+                }
+                None => pending().await,
+            }
+        };
+
+        tokio::select! {
+            next = tx_stream.next() => {
+                let Some(pending) = next else {
+                    return Ok(Interruption::Closed);
+                };
+                last_seen = Instant::now();
+                backoff.reset();
+                pipeline.submit(pending).await;
+            }
+            block = blocks.next() => {
+                let Some(block) = block else {
+                    return Ok(Interruption::Closed);
+                };
+                if let Some(number) = block.number {
+                    pipeline.mined(number, block.next_block_base_fee());
+                }
+            }
+            _ = report_due => pipeline.report(),
+            _ = stalled => return Ok(Interruption::Stalled(stall_timeout.unwrap_or_default())),
+            error = pipeline.failed() => return Err(error),
+        }
+    }
+}
+
+async fn reconnect(chain: Chain, backoff: &mut Backoff, reason: impl fmt::Display) {
+    let delay = backoff.next_delay();
+    warn!(
+        "{}: {}, reconnecting in {:?} (attempt {})",
+        chain,
+        reason,
+        delay,
+        backoff.attempt()
+    );
+    tokio::time::sleep(delay).await;
+}